@@ -0,0 +1,401 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use wasm_bindgen::prelude::*;
+use crate::{Pour, WaterSorting};
+use crate::proof::{canonical_key, explore};
+use crate::strategy::{color_runs, PruningRules};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinimumEmptyBottles {
+    pub empty_bottles: usize,
+    // the analysed layout followed by `empty_bottles` empty ones, `solution` refers to its indices
+    pub level: WaterSorting,
+    pub solution: Vec<Pour>,
+    // smaller counts of empty bottles that could not be ruled out within `max_nodes`
+    pub undecided: Vec<usize>,
+}
+
+/// Finds the smallest number of empty bottles (up to `max_empty`) that makes the filled and locked
+/// bottles of `level` solvable. The added bottles are as large as the largest bottle of `level`. Every count is decided by an exhaustive search, so the returned solution is also
+/// a shortest one for that count.
+pub fn minimum_empty_bottles(level: &WaterSorting, max_empty: usize, max_nodes: usize) -> Option<MinimumEmptyBottles> {
+    let filled = level.without_empty_bottles();
+    let capacity = level.capacity();
+    let mut undecided = Vec::new();
+    for empty_bottles in 0..=max_empty {
+        let mut candidate = filled.clone();
+        (0..empty_bottles).for_each(|_| candidate.init_empty_bottle_with_capacity(capacity));
+        let report = explore(&candidate, max_nodes, false);
+        if let Some(solution) = report.solution() {
+            return Some(MinimumEmptyBottles { empty_bottles, solution: solution.to_vec(), level: candidate, undecided });
+        }
+        if !report.closed {
+            undecided.push(empty_bottles);
+        }
+    }
+    None
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DifficultyRating {
+    // 0 (trivial) to 100 (hardest), see `rate_difficulty`
+    pub score: f64,
+    pub solvable: bool,
+    // the whole reachable state space fit into `max_nodes`
+    pub exhaustive: bool,
+    pub optimal_length: usize,
+    pub state_space: usize,
+    // pours from a state that can still be won into one that cannot
+    pub dead_ends: usize,
+    // share of dead ends among all pours available in winnable states
+    pub dead_end_ratio: f64,
+    // 0 when every color forms a single run, 1 when no two neighbouring layers match
+    pub fragmentation: f64,
+    // pours of the optimal solution that were the only way to keep the level winnable
+    pub forced_moves: usize,
+    pub length_points: f64,
+    pub state_space_points: f64,
+    pub dead_end_points: f64,
+    pub fragmentation_points: f64,
+    pub decision_points: f64,
+}
+
+impl Display for DifficultyRating {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Difficulty: {:.1} / 100", self.score)?;
+        writeln!(f, "  optimal length {:>4} -> {:>5.1} pts", self.optimal_length, self.length_points)?;
+        writeln!(f, "  state space {:>7} -> {:>5.1} pts", self.state_space, self.state_space_points)?;
+        writeln!(f, "  dead ends {:>5} ({:.0}%) -> {:>5.1} pts", self.dead_ends, self.dead_end_ratio * 100.0, self.dead_end_points)?;
+        writeln!(f, "  fragmentation {:>5.2} -> {:>5.1} pts", self.fragmentation, self.fragmentation_points)?;
+        write!(f, "  forced moves {:>5} -> {:>5.1} pts", self.forced_moves, self.decision_points)
+    }
+}
+
+// weights of the components, they add up to 100
+const LENGTH_WEIGHT: f64 = 35.0;
+const STATE_SPACE_WEIGHT: f64 = 20.0;
+const DEAD_END_WEIGHT: f64 = 25.0;
+const FRAGMENTATION_WEIGHT: f64 = 10.0;
+const DECISION_WEIGHT: f64 = 10.0;
+// optimal length and state space size at which those components max out
+const LENGTH_CAP: f64 = 40.0;
+const STATE_SPACE_CAP: f64 = 1_000_000.0;
+
+/// Rates `level` on a 0-100 scale as a weighted sum of five components, each scaled to 0..1:
+///
+/// * 35 pts: optimal solution length, `length / 40`
+/// * 20 pts: reachable state space, `log10(states) / 6`
+/// * 25 pts: share of pours from winnable states leading to states that can no longer be won
+/// * 10 pts: color fragmentation of the starting layout
+/// * 10 pts: share of pours on the optimal path that were a real choice (not forced)
+///
+/// Components are capped at 1. Unsolvable levels score 0. The state space is explored up to
+/// `max_nodes` states; beyond that unexplored states are assumed winnable and `exhaustive` is false.
+pub fn rate_difficulty(level: &WaterSorting, max_nodes: usize) -> DifficultyRating {
+    let graph = StateGraph::explore(level, max_nodes);
+    let alive = graph.winnable();
+
+    let (mut dead_ends, mut branches) = (0, 0);
+    for (_, children) in graph.children.iter().enumerate().filter(|(node, _)| alive[*node]) {
+        branches += children.len();
+        dead_ends += children.iter().filter(|&&child| !alive[child]).count();
+    }
+    let dead_end_ratio = if branches == 0 { 0.0 } else { dead_ends as f64 / branches as f64 };
+
+    let (runs, colors) = color_runs(level);
+    let layers = level.bottles.iter().map(|b| b.layers().iter().flatten().count()).sum::<usize>();
+    let fragmentation = if layers > colors { (runs - colors) as f64 / (layers - colors) as f64 } else { 0.0 };
+
+    let solution = graph.shortest_win();
+    let optimal_length = solution.as_ref().map_or(0, |path| path.len() - 1);
+    let forced_moves = solution.as_ref().map_or(0, |path| {
+        path[..path.len() - 1]
+            .iter()
+            .filter(|&&node| graph.children[node].iter().filter(|&&child| alive[child]).count() == 1)
+            .count()
+    });
+
+    let solvable = solution.is_some();
+    let scale = |value: f64, weight: f64| if solvable { value.clamp(0.0, 1.0) * weight } else { 0.0 };
+    let length_points = scale(optimal_length as f64 / LENGTH_CAP, LENGTH_WEIGHT);
+    let state_space_points = scale((graph.children.len() as f64).log10() / STATE_SPACE_CAP.log10(), STATE_SPACE_WEIGHT);
+    let dead_end_points = scale(dead_end_ratio, DEAD_END_WEIGHT);
+    let fragmentation_points = scale(fragmentation, FRAGMENTATION_WEIGHT);
+    let decisions = if optimal_length == 0 { 0.0 } else { 1.0 - forced_moves as f64 / optimal_length as f64 };
+    let decision_points = scale(decisions, DECISION_WEIGHT);
+
+    DifficultyRating {
+        score: length_points + state_space_points + dead_end_points + fragmentation_points + decision_points,
+        solvable,
+        exhaustive: graph.closed,
+        optimal_length,
+        state_space: graph.children.len(),
+        dead_ends,
+        dead_end_ratio,
+        fragmentation,
+        forced_moves,
+        length_points,
+        state_space_points,
+        dead_end_points,
+        fragmentation_points,
+        decision_points,
+    }
+}
+
+#[wasm_bindgen]
+pub fn difficulty_rating(level: &WaterSorting, max_nodes: usize) -> DifficultyRating {
+    rate_difficulty(level, max_nodes)
+}
+
+// reachable states (up to bottle order) with the pours between them, in breadth-first order
+struct StateGraph {
+    children: Vec<Vec<usize>>,
+    parent: Vec<usize>,
+    winning: Vec<bool>,
+    expanded: Vec<bool>,
+    closed: bool,
+}
+
+impl StateGraph {
+    fn explore(level: &WaterSorting, max_nodes: usize) -> Self {
+        let mut graph = StateGraph { children: vec![Vec::new()], parent: vec![0], winning: vec![false], expanded: vec![false], closed: true };
+        let mut ids = HashMap::new();
+        ids.insert(canonical_key(level), 0usize);
+        let mut queue = VecDeque::new();
+        queue.push_back((level.clone(), 0usize));
+        let pruning = PruningRules { deduplicate_states: true, ..PruningRules::none() };
+
+        while let Some((state, node)) = queue.pop_front() {
+            if state.win() {
+                graph.winning[node] = true;
+                continue;
+            }
+            if ids.len() >= max_nodes {
+                graph.closed = false;
+                continue;
+            }
+            graph.expanded[node] = true;
+            for pour in state.possible_moves(&pruning) {
+                let mut child = state.clone();
+                child.pour(pour.from, pour.to);
+                child.old_state = None;
+                let key = canonical_key(&child);
+                let id = match ids.get(&key) {
+                    Some(&id) => id,
+                    None => {
+                        let id = graph.children.len();
+                        ids.insert(key, id);
+                        graph.children.push(Vec::new());
+                        graph.parent.push(node);
+                        graph.winning.push(false);
+                        graph.expanded.push(false);
+                        queue.push_back((child, id));
+                        id
+                    }
+                };
+                if id != node && !graph.children[node].contains(&id) {
+                    graph.children[node].push(id);
+                }
+            }
+        }
+        graph
+    }
+
+    // states from which a win can still be reached, unexplored ones are assumed to be winnable
+    fn winnable(&self) -> Vec<bool> {
+        let mut parents = vec![Vec::new(); self.children.len()];
+        for (node, children) in self.children.iter().enumerate() {
+            children.iter().for_each(|&child| parents[child].push(node));
+        }
+        let mut alive = (0..self.children.len()).map(|n| self.winning[n] || !self.expanded[n]).collect::<Vec<_>>();
+        let mut queue = (0..self.children.len()).filter(|&n| alive[n]).collect::<VecDeque<_>>();
+        while let Some(node) = queue.pop_front() {
+            for &parent in &parents[node] {
+                if !alive[parent] {
+                    alive[parent] = true;
+                    queue.push_back(parent);
+                }
+            }
+        }
+        alive
+    }
+
+    // states on the shortest path from the start to a win, both included
+    fn shortest_win(&self) -> Option<Vec<usize>> {
+        let mut node = (0..self.children.len()).find(|&n| self.winning[n])?;
+        let mut path = vec![node];
+        while node != 0 {
+            node = self.parent[node];
+            path.push(node);
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod minimum_empty_bottles_tests {
+    use crate::{Color, WaterSorting};
+    use crate::analysis::minimum_empty_bottles;
+
+    #[test]
+    fn already_sorted_level_needs_no_empty_bottle() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Red);
+        w.init_empty_bottle();
+
+        let result = minimum_empty_bottles(&w, 2, usize::MAX).unwrap();
+
+        assert_eq!(result.empty_bottles, 0);
+        assert!(result.solution.is_empty());
+        assert_eq!(result.level.bottles_count(), 1);
+    }
+
+    #[test]
+    fn two_swapped_colors_need_one_empty_bottle() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Green, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Green);
+        w.init_empty_bottle();
+        w.init_empty_bottle();
+
+        let result = minimum_empty_bottles(&w, 3, usize::MAX).unwrap();
+
+        assert_eq!(result.empty_bottles, 1);
+        assert!(result.undecided.is_empty());
+        let mut replay = result.level.clone();
+        result.solution.iter().for_each(|p| replay.pour(p.from, p.to));
+        assert!(replay.win());
+    }
+
+    #[test]
+    fn empty_bottles_match_the_capacity_of_the_level() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_colors(3, &[Color::Green, Color::Green, Color::Red]);
+        w.init_bottle_with_colors(3, &[Color::Red, Color::Red, Color::Green]);
+        w.init_empty_bottle_with_capacity(3);
+
+        let result = minimum_empty_bottles(&w, 2, usize::MAX).unwrap();
+
+        assert_eq!(result.empty_bottles, 1);
+        assert_eq!(result.level.capacity(), 3);
+        let mut replay = result.level.clone();
+        result.solution.iter().for_each(|p| replay.pour(p.from, p.to));
+        assert!(replay.win());
+    }
+
+    #[test]
+    fn level_that_needs_two_empty_bottles() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Blue, Color::Green, Color::Red, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Blue, Color::Red);
+        w.init_bottle_with_four_colors(Color::Green, Color::Red, Color::Blue, Color::Red);
+
+        let result = minimum_empty_bottles(&w, 3, usize::MAX).unwrap();
+
+        assert_eq!(result.empty_bottles, 2);
+    }
+
+    #[test]
+    fn locked_empty_bottles_are_kept() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Green, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Green);
+        w.init_empty_bottle();
+        w.lock_bottle_to_color(2, Color::Red);
+
+        let result = minimum_empty_bottles(&w, 2, usize::MAX).unwrap();
+
+        assert_eq!(result.empty_bottles, 0);
+        assert_eq!(result.level.bottle_color_lock(2), Some(Color::Red));
+    }
+
+    #[test]
+    fn no_result_when_limit_is_too_low() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Blue, Color::Red, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Red, Color::Blue, Color::Red);
+
+        assert!(minimum_empty_bottles(&w, 0, usize::MAX).is_none());
+    }
+}
+
+#[cfg(test)]
+mod difficulty_tests {
+    use crate::{Color, WaterSorting};
+    use crate::analysis::rate_difficulty;
+    use crate::generator::{generate, GeneratorParams};
+
+    fn easy() -> WaterSorting {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Green, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Green);
+        w.init_empty_bottle();
+        w
+    }
+
+    #[test]
+    fn breakdown_adds_up_to_the_score() {
+        let rating = rate_difficulty(&easy(), usize::MAX);
+
+        let sum = rating.length_points + rating.state_space_points + rating.dead_end_points +
+            rating.fragmentation_points + rating.decision_points;
+        assert!((rating.score - sum).abs() < 1e-9);
+        assert!(rating.score > 0.0 && rating.score <= 100.0);
+        assert!(rating.solvable);
+        assert!(rating.exhaustive);
+        assert_eq!(rating.optimal_length, 3);
+    }
+
+    #[test]
+    fn solved_level_is_trivial() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Red);
+        w.init_empty_bottle();
+
+        let rating = rate_difficulty(&w, usize::MAX);
+
+        assert_eq!(rating.optimal_length, 0);
+        assert_eq!(rating.fragmentation, 0.0);
+        assert!(rating.score < 5.0);
+    }
+
+    #[test]
+    fn unsolvable_level_scores_zero() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Blue, Color::Green, Color::Red, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Blue, Color::Red);
+        w.init_bottle_with_four_colors(Color::Green, Color::Red, Color::Blue, Color::Red);
+        w.init_empty_bottle();
+
+        let rating = rate_difficulty(&w, usize::MAX);
+
+        assert!(!rating.solvable);
+        assert_eq!(rating.score, 0.0);
+    }
+
+    #[test]
+    fn bigger_level_is_harder() {
+        let small = generate(&GeneratorParams::new(3, 2, 4, 11)).unwrap().level;
+        let big = generate(&GeneratorParams::new(6, 2, 4, 11)).unwrap().level;
+
+        assert!(rate_difficulty(&big, 200_000).score > rate_difficulty(&small, 200_000).score);
+    }
+
+    #[test]
+    fn forced_moves_are_counted_on_the_optimal_path() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Blue, Color::Red);
+        w.init_empty_bottle();
+
+        let rating = rate_difficulty(&w, usize::MAX);
+
+        // the first pour leads to two different states, every later pour to a single one (up to bottle order)
+        assert_eq!(rating.optimal_length, 3);
+        assert_eq!(rating.forced_moves, 2);
+        assert_eq!(rating.dead_ends, 0);
+        assert_eq!(rating.dead_end_ratio, 0.0);
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use wasm_bindgen::prelude::*;
+use crate::{Color, WaterSolver, WaterSorting};
+use crate::pack::{LevelPack, PackError};
+use crate::proof::fnv1a;
+use crate::scoring::{star_rating, StarRating, StarThresholds};
+
+// prefix of every save blob, bumped when the layout changes
+const SAVE_VERSION: &str = "wsc1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CampaignError {
+    Pack(PackError),
+    Io(String),
+    UnknownLevel(usize),
+    Locked(usize),
+    NotWon,
+    // the game was not played on the level it is recorded for
+    WrongLevel(usize),
+    // the save blob is damaged or from another version
+    CorruptSave(String),
+    // the save blob belongs to another pack
+    WrongPack,
+}
+
+impl Display for CampaignError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CampaignError::Pack(e) => write!(f, "{}", e),
+            CampaignError::Io(reason) => write!(f, "cannot access save: {}", reason),
+            CampaignError::UnknownLevel(index) => write!(f, "there is no level {} in the campaign", index + 1),
+            CampaignError::Locked(index) => write!(f, "level {} is still locked", index + 1),
+            CampaignError::NotWon => write!(f, "the game is not won yet"),
+            CampaignError::WrongLevel(index) => write!(f, "the game was not played on level {}", index + 1),
+            CampaignError::CorruptSave(reason) => write!(f, "save is corrupt: {}", reason),
+            CampaignError::WrongPack => write!(f, "save belongs to another pack"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct LevelProgress {
+    pub best_moves: Option<usize>,
+    pub stars: u8,
+}
+
+impl LevelProgress {
+    pub fn is_solved(&self) -> bool {
+        self.best_moves.is_some()
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Campaign {
+    pack: LevelPack,
+    progress: Vec<LevelProgress>,
+    // levels open from the start, every solved level unlocks the next one
+    pub initially_unlocked: usize,
+    thresholds: StarThresholds,
+}
+
+#[wasm_bindgen]
+impl Campaign {
+    /// Campaign over a pack given as text, `None` when the pack is invalid.
+    pub fn from_pack_text(text: &str) -> Option<Campaign> {
+        text.parse().ok().map(Campaign::new)
+    }
+
+    /// Campaign over the levels bundled with the game.
+    pub fn classic() -> Campaign {
+        Campaign::new(LevelPack::classic())
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.pack.levels.len()
+    }
+
+    pub fn level(&self, index: usize) -> Option<WaterSorting> {
+        self.pack.levels.get(index)?.build().ok()
+    }
+
+    /// Par recorded in the pack for the level at `index`, `None` when the pack has none.
+    pub fn par(&self, index: usize) -> Option<usize> {
+        self.pack.levels.get(index)?.par_moves
+    }
+
+    pub fn is_unlocked(&self, index: usize) -> bool {
+        index < self.level_count() && (index < self.initially_unlocked.max(1) || self.progress[index - 1].is_solved())
+    }
+
+    pub fn is_solved(&self, index: usize) -> bool {
+        self.progress.get(index).is_some_and(LevelProgress::is_solved)
+    }
+
+    pub fn best_moves(&self, index: usize) -> Option<usize> {
+        self.progress.get(index)?.best_moves
+    }
+
+    pub fn stars(&self, index: usize) -> u8 {
+        self.progress.get(index).map_or(0, |progress| progress.stars)
+    }
+
+    pub fn total_stars(&self) -> usize {
+        self.progress.iter().map(|progress| progress.stars as usize).sum()
+    }
+
+    /// First unlocked level that is not solved yet, `None` once the campaign is complete.
+    pub fn next_level(&self) -> Option<usize> {
+        (0..self.level_count()).find(|&index| self.is_unlocked(index) && !self.is_solved(index))
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: &StarThresholds) {
+        self.thresholds = *thresholds;
+    }
+
+    /// Records a won game of the level at `index`, `None` when it cannot be recorded or earns no stars.
+    pub fn complete(&mut self, index: usize, game: &WaterSorting) -> Option<StarRating> {
+        self.record_win(index, game).ok().flatten()
+    }
+
+    pub fn save_blob(&self) -> String {
+        self.save()
+    }
+
+    /// Restores progress from `save_blob`, keeping the current progress when the blob is rejected.
+    pub fn restore_blob(&mut self, blob: &str) -> bool {
+        self.restore(blob).is_ok()
+    }
+}
+
+// level ids are free text, so the separators of the save blob are escaped
+fn escape(id: &str) -> String {
+    id.chars().map(|c| match c {
+        '%' | ',' | '=' | ':' => format!("%{:02x}", c as u32),
+        c => c.to_string(),
+    }).collect()
+}
+
+fn unescape(id: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut chars = id.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            let code = chars.next()?.to_digit(16)? * 16 + chars.next()?.to_digit(16)?;
+            result.push(char::from_u32(code)?);
+        } else {
+            result.push(c);
+        }
+    }
+    Some(result)
+}
+
+// liquid of each color in the bottles, pours only move it around
+fn liquid(level: &WaterSorting) -> HashMap<Color, usize> {
+    let mut liquid = HashMap::new();
+    for layer in level.bottles.iter().flat_map(|b| b.layers()).flatten() {
+        *liquid.entry(layer).or_insert(0) += 1;
+    }
+    liquid
+}
+
+// whether `game` can have started as `level`: the same bottles, followed by any extra ones,
+// holding the same liquid
+fn is_game_of(game: &WaterSorting, level: &WaterSorting) -> bool {
+    game.bottles.len() == level.bottles.len() + game.extra_bottles_used
+        && game.bottles.iter().zip(&level.bottles).all(|(played, b)| played.capacity == b.capacity && played.color_lock == b.color_lock)
+        && liquid(game) == liquid(level)
+}
+
+impl Campaign {
+    pub fn new(pack: LevelPack) -> Self {
+        let progress = vec![LevelProgress::default(); pack.levels.len()];
+        Campaign { pack, progress, initially_unlocked: 1, thresholds: StarThresholds::new() }
+    }
+
+    pub fn pack(&self) -> &LevelPack {
+        &self.pack
+    }
+
+    pub fn progress(&self, index: usize) -> Option<&LevelProgress> {
+        self.progress.get(index)
+    }
+
+    /// Stores the result of a won game, keeping the best move count and stars of earlier wins.
+    /// Stars are awarded against the pack `par`, or against the solver when the pack has none;
+    /// when the solver cannot find par either the win is recorded without stars and `None` is returned.
+    pub fn record_win(&mut self, index: usize, game: &WaterSorting) -> Result<Option<StarRating>, CampaignError> {
+        let level = self.pack.levels.get(index).ok_or(CampaignError::UnknownLevel(index))?;
+        if !self.is_unlocked(index) {
+            return Err(CampaignError::Locked(index));
+        }
+        if !game.win() {
+            return Err(CampaignError::NotWon);
+        }
+        let start = level.build().map_err(CampaignError::Pack)?;
+        if !is_game_of(game, &start) {
+            return Err(CampaignError::WrongLevel(index));
+        }
+        let par = level.par_moves.or_else(|| WaterSolver::new(&start).par());
+        let rating = par.and_then(|par| star_rating(game, par, &self.thresholds));
+        let progress = &mut self.progress[index];
+        let moves = game.moves_made();
+        progress.best_moves = Some(progress.best_moves.map_or(moves, |best| best.min(moves)));
+        if let Some(rating) = rating {
+            progress.stars = progress.stars.max(rating.stars);
+        }
+        Ok(rating)
+    }
+
+    // `wsc1:<pack name digest>:<id>=<best moves>*<stars>,...`, only solved levels are listed
+    pub fn save(&self) -> String {
+        let levels = self.pack.levels
+            .iter()
+            .zip(&self.progress)
+            .filter_map(|(level, progress)| {
+                progress.best_moves.map(|moves| format!("{}={}*{}", escape(&level.id), moves, progress.stars))
+            })
+            .collect::<Vec<_>>();
+        format!("{}:{:016x}:{}", SAVE_VERSION, fnv1a(self.pack.name.as_bytes()), levels.join(","))
+    }
+
+    /// Replaces the progress with the one in `blob`. Levels no longer in the pack are skipped.
+    pub fn restore(&mut self, blob: &str) -> Result<(), CampaignError> {
+        let corrupt = |reason: &str| CampaignError::CorruptSave(reason.to_string());
+        let mut parts = blob.trim().splitn(3, ':');
+        if parts.next() != Some(SAVE_VERSION) {
+            return Err(corrupt("unknown version"));
+        }
+        let digest = parts.next().and_then(|digest| u64::from_str_radix(digest, 16).ok()).ok_or_else(|| corrupt("missing pack digest"))?;
+        if digest != fnv1a(self.pack.name.as_bytes()) {
+            return Err(CampaignError::WrongPack);
+        }
+        let mut progress = vec![LevelProgress::default(); self.pack.levels.len()];
+        for entry in parts.next().unwrap_or("").split(',').filter(|entry| !entry.is_empty()) {
+            let (id, result) = entry.split_once('=').ok_or_else(|| corrupt(entry))?;
+            let (moves, stars) = result.split_once('*').ok_or_else(|| corrupt(entry))?;
+            let id = unescape(id).ok_or_else(|| corrupt(entry))?;
+            let moves = moves.parse().map_err(|_| corrupt(entry))?;
+            let stars = stars.parse::<u8>().ok().filter(|&stars| stars <= 3).ok_or_else(|| corrupt(entry))?;
+            if let Some(index) = self.pack.levels.iter().position(|level| level.id == id) {
+                progress[index] = LevelProgress { best_moves: Some(moves), stars };
+            }
+        }
+        self.progress = progress;
+        Ok(())
+    }
+
+    /// Restores progress saved with `save_file`, a missing file leaves the campaign untouched.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), CampaignError> {
+        match std::fs::read_to_string(path) {
+            Ok(blob) => self.restore(&blob),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(CampaignError::Io(e.to_string())),
+        }
+    }
+
+    pub fn save_file(&self, path: impl AsRef<Path>) -> Result<(), CampaignError> {
+        std::fs::write(path, self.save()).map_err(|e| CampaignError::Io(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod campaign_tests {
+    use crate::WaterSorting;
+    use crate::campaign::{Campaign, CampaignError};
+    use crate::pack::LevelPack;
+
+    const PACK: &str = "water-sort-pack 1
+name = Tiny
+author = pawlos
+
+[level]
+id = one
+name = One
+par = 3
+bottle = red red red blue
+bottle = blue blue blue red
+bottle =
+bottle =
+
+[level]
+id = two, really
+name = Two
+bottle = green green green red
+bottle = red red red green
+bottle =
+bottle =
+
+[level]
+id = three
+name = Three
+par = 3
+bottle = red red red blue
+bottle = blue blue blue red
+bottle =
+bottle =
+";
+
+    fn campaign() -> Campaign {
+        Campaign::from_pack_text(PACK).unwrap()
+    }
+
+    // every level is solved by 0 -> 2, 1 -> 0, 2 -> 1, each wasted round adds two moves
+    fn won(campaign: &Campaign, index: usize, wasted: usize) -> WaterSorting {
+        let mut w = campaign.level(index).unwrap();
+        w.pour(0, 2);
+        for _ in 0..wasted {
+            w.pour(2, 3);
+            w.pour(3, 2);
+        }
+        w.pour(1, 0);
+        w.pour(2, 1);
+        assert!(w.win());
+        w
+    }
+
+    #[test]
+    fn only_the_first_level_is_unlocked_at_start() {
+        let campaign = campaign();
+
+        assert!(campaign.is_unlocked(0));
+        assert!(!campaign.is_unlocked(1));
+        assert!(!campaign.is_unlocked(3));
+        assert_eq!(campaign.next_level(), Some(0));
+    }
+
+    #[test]
+    fn par_comes_from_the_pack() {
+        let campaign = campaign();
+
+        assert_eq!(campaign.par(0), Some(3));
+        assert_eq!(campaign.par(1), None);
+        assert_eq!(campaign.par(3), None);
+    }
+
+    #[test]
+    fn solving_a_level_unlocks_the_next_one() {
+        let mut campaign = campaign();
+        let game = won(&campaign, 0, 0);
+
+        let rating = campaign.record_win(0, &game).unwrap().unwrap();
+
+        assert_eq!(rating.stars, 3);
+        assert!(campaign.is_solved(0));
+        assert!(campaign.is_unlocked(1));
+        assert!(!campaign.is_unlocked(2));
+        assert_eq!(campaign.next_level(), Some(1));
+    }
+
+    #[test]
+    fn locked_and_unfinished_levels_are_not_recorded() {
+        let mut campaign = campaign();
+        let game = won(&campaign, 1, 0);
+
+        assert_eq!(campaign.record_win(1, &game), Err(CampaignError::Locked(1)));
+        assert_eq!(campaign.record_win(0, &campaign.level(0).unwrap()), Err(CampaignError::NotWon));
+        assert_eq!(campaign.record_win(5, &game), Err(CampaignError::UnknownLevel(5)));
+    }
+
+    #[test]
+    fn best_result_is_kept() {
+        let mut campaign = campaign();
+
+        campaign.record_win(0, &won(&campaign, 0, 0)).unwrap();
+        campaign.record_win(0, &won(&campaign, 0, 2)).unwrap();
+
+        assert_eq!(campaign.best_moves(0), Some(3));
+        assert_eq!(campaign.stars(0), 3);
+    }
+
+    #[test]
+    fn par_falls_back_to_the_solver() {
+        let mut campaign = campaign();
+        campaign.record_win(0, &won(&campaign, 0, 0)).unwrap();
+
+        let rating = campaign.record_win(1, &won(&campaign, 1, 0)).unwrap().unwrap();
+
+        assert_eq!((rating.par, rating.stars), (3, 3));
+    }
+
+    #[test]
+    fn win_without_par_earns_no_stars() {
+        // only winnable with the extra bottle, which the solver never adds
+        let mut campaign = Campaign::from_pack_text("water-sort-pack 1
+name = Spare
+author = pawlos
+
+[level]
+id = spare
+name = Spare
+rules = extra=1
+bottle = green green green red
+bottle = red red red green
+").unwrap();
+        let mut game = campaign.level(0).unwrap();
+        assert!(game.add_extra_bottle(4));
+        game.pour(0, 2);
+        game.pour(1, 0);
+        game.pour(2, 1);
+
+        assert_eq!(campaign.record_win(0, &game), Ok(None));
+        assert_eq!(campaign.best_moves(0), Some(3));
+        assert_eq!(campaign.stars(0), 0);
+    }
+
+    #[test]
+    fn game_of_another_level_is_rejected() {
+        let mut campaign = campaign();
+        let game = won(&campaign, 1, 0);
+
+        assert_eq!(campaign.record_win(0, &game), Err(CampaignError::WrongLevel(0)));
+        assert!(!campaign.is_solved(0));
+    }
+
+    #[test]
+    fn progress_survives_a_save_round_trip() {
+        let mut campaign = campaign();
+        campaign.record_win(0, &won(&campaign, 0, 0)).unwrap();
+        campaign.record_win(1, &won(&campaign, 1, 1)).unwrap();
+
+        let blob = campaign.save_blob();
+        let mut restored = self::campaign();
+
+        assert!(restored.restore_blob(&blob));
+        assert_eq!(restored, campaign);
+        assert_eq!(restored.total_stars(), 5);
+        assert_eq!(restored.next_level(), Some(2));
+        assert!(blob.len() < 60);
+    }
+
+    #[test]
+    fn save_from_another_pack_or_version_is_rejected() {
+        let mut campaign = campaign();
+        campaign.record_win(0, &won(&campaign, 0, 0)).unwrap();
+        let other = Campaign::new(LevelPack { name: "Other".to_string(), ..campaign.pack().clone() });
+
+        assert_eq!(campaign.clone().restore(&other.save()), Err(CampaignError::WrongPack));
+        assert!(matches!(campaign.clone().restore("wsc0:00:"), Err(CampaignError::CorruptSave(_))));
+        assert!(!campaign.restore_blob(&campaign.save().replace("*3", "*9")));
+        assert!(campaign.is_solved(0));
+    }
+
+    #[test]
+    fn classic_campaign_is_bundled() {
+        let campaign = Campaign::classic();
+
+        assert_eq!(campaign.level_count(), 2);
+        assert!(campaign.level(0).unwrap().can_be_sorted());
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt::{Display, Formatter};
+use wasm_bindgen::prelude::*;
+use crate::{Pour, WaterSorting};
+use crate::generator::{generate, GenerationConstraints, GeneratorError, GeneratorParams};
+use crate::proof::fnv1a;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Display for Weekday {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+const WEEK: [Weekday; 7] = [
+    Weekday::Monday,
+    Weekday::Tuesday,
+    Weekday::Wednesday,
+    Weekday::Thursday,
+    Weekday::Friday,
+    Weekday::Saturday,
+    Weekday::Sunday,
+];
+
+// colors of the puzzle on each day, from an easy Monday to a hard Sunday
+const COLORS_BY_WEEKDAY: [usize; 7] = [4, 5, 6, 7, 8, 9, 10];
+const DAILY_EMPTY_BOTTLES: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyPuzzle {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub weekday: Weekday,
+    pub params: GeneratorParams,
+    pub level: WaterSorting,
+    pub solution: Vec<Pour>,
+}
+
+// days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year as i64 - 1 } else { year as i64 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+pub fn weekday(year: i32, month: u32, day: u32) -> Weekday {
+    // 1970-01-01 was a Thursday
+    WEEK[(days_from_civil(year, month, day) + 3).rem_euclid(7) as usize]
+}
+
+/// Parameters of the puzzle for a date. The seed only depends on the date, so every player gets
+/// the same level.
+pub fn daily_params(year: i32, month: u32, day: u32) -> GeneratorParams {
+    let weekday = weekday(year, month, day);
+    let seed = fnv1a(format!("{:04}-{:02}-{:02}", year, month, day).as_bytes());
+    let constraints = GenerationConstraints { no_solved_bottles: true, ..GenerationConstraints::new() };
+    GeneratorParams::new(COLORS_BY_WEEKDAY[weekday as usize], DAILY_EMPTY_BOTTLES, 4, seed).with_constraints(constraints)
+}
+
+pub fn daily_puzzle(year: i32, month: u32, day: u32) -> Result<DailyPuzzle, GeneratorError> {
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(GeneratorError::InvalidParams(format!("{:04}-{:02}-{:02} is not a valid date", year, month, day)));
+    }
+    let params = daily_params(year, month, day);
+    let generated = generate(&params)?;
+    Ok(DailyPuzzle {
+        year,
+        month,
+        day,
+        weekday: weekday(year, month, day),
+        params,
+        level: generated.level,
+        solution: generated.solution,
+    })
+}
+
+#[wasm_bindgen]
+pub fn daily_level(year: i32, month: u32, day: u32) -> Option<WaterSorting> {
+    daily_puzzle(year, month, day).ok().map(|puzzle| puzzle.level)
+}
+
+#[wasm_bindgen]
+pub fn daily_weekday(year: i32, month: u32, day: u32) -> Weekday {
+    weekday(year, month, day)
+}
+
+#[cfg(test)]
+mod daily_tests {
+    use crate::daily::{daily_params, daily_puzzle, weekday, Weekday};
+    use crate::generator::GeneratorError;
+
+    #[test]
+    fn weekdays_match_the_calendar() {
+        assert_eq!(weekday(1970, 1, 1), Weekday::Thursday);
+        assert_eq!(weekday(2000, 2, 29), Weekday::Tuesday);
+        assert_eq!(weekday(2024, 1, 1), Weekday::Monday);
+        assert_eq!(weekday(1969, 12, 31), Weekday::Wednesday);
+    }
+
+    #[test]
+    fn same_date_gives_the_same_puzzle() {
+        let first = daily_puzzle(2024, 3, 11).unwrap();
+        let second = daily_puzzle(2024, 3, 11).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(first.weekday, Weekday::Monday);
+    }
+
+    #[test]
+    fn consecutive_dates_give_different_puzzles() {
+        let monday = daily_puzzle(2024, 3, 11).unwrap();
+        let next_monday = daily_puzzle(2024, 3, 18).unwrap();
+
+        assert_ne!(monday.level, next_monday.level);
+    }
+
+    #[test]
+    fn daily_puzzle_is_solvable() {
+        let puzzle = daily_puzzle(2024, 3, 12).unwrap();
+        let mut w = puzzle.level.clone();
+
+        assert!(!w.win());
+        puzzle.solution.iter().for_each(|p| w.pour(p.from, p.to));
+        assert!(w.win());
+    }
+
+    #[test]
+    fn difficulty_grows_over_the_week() {
+        let colors = (11..=17).map(|day| daily_params(2024, 3, day).colors).collect::<Vec<_>>();
+
+        assert!(colors.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(daily_params(2024, 3, 18).colors, colors[0]);
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        assert!(matches!(daily_puzzle(2023, 2, 29), Err(GeneratorError::InvalidParams(_))));
+        assert!(matches!(daily_puzzle(2024, 13, 1), Err(GeneratorError::InvalidParams(_))));
+        assert!(matches!(daily_puzzle(2024, 4, 31), Err(GeneratorError::InvalidParams(_))));
+        assert!(daily_puzzle(2024, 2, 29).is_ok());
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use wasm_bindgen::prelude::*;
+use crate::{Color, WaterSorting};
+use crate::pack::LevelPack;
+
+// a bottle as capacity and frozen layers followed by its color lock and its layers, bottom first
+type RawBottle = ((usize, u8), [Option<Color>; 5]);
+
+// partial canonical order: bottles not placed yet and the labels given to colors so far
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Partial {
+    remaining: Vec<RawBottle>,
+    labels: Vec<(Color, u8)>,
+}
+
+impl Partial {
+    // colors not labelled yet get the next free label in order of appearance,
+    // rainbow is not a color that can be renamed and keeps a label of its own
+    fn encode(&self, bottle: &RawBottle) -> ([u8; 7], Vec<(Color, u8)>) {
+        let mut labels = self.labels.clone();
+        let mut encoded = [bottle.0.0 as u8, bottle.0.1, 0, 0, 0, 0, 0];
+        for (slot, layer) in bottle.1.iter().enumerate() {
+            if let Some(color) = layer {
+                let label = match labels.iter().find(|(c, _)| c == color) {
+                    _ if *color == Color::Rainbow => u8::MAX,
+                    Some(&(_, label)) => label,
+                    None => {
+                        let label = labels.len() as u8 + 1;
+                        labels.push((*color, label));
+                        label
+                    }
+                };
+                encoded[slot + 2] = label;
+            }
+        }
+        (encoded, labels)
+    }
+}
+
+/// Encoding of `level` that is the same for every level that only differs in the order of its
+/// bottles and the naming of its colors: the rules followed by the smallest bottle by bottle
+/// encoding over all bottle orders, with colors numbered in order of first appearance.
+pub fn canonical_form(level: &WaterSorting) -> Vec<u8> {
+    let mut remaining = level.bottles.iter().map(|b| {
+        let [bottom, l1, l2, top] = b.layers();
+        ((b.capacity, b.frozen_mask()), [b.color_lock, bottom, l1, l2, top])
+    }).collect::<Vec<RawBottle>>();
+    remaining.sort_by_key(|(shape, layers)| (*shape, layers.map(WaterSorting::map_color_to_u8)));
+    let mut frontier = vec![Partial { remaining, labels: Vec::new() }];
+    let mut form = level.rules().to_string().into_bytes();
+    if level.has_frozen_layers() {
+        form.extend(format!(" thaw={}", level.thaw_trigger()).bytes());
+    }
+
+    // every step places the smallest possible next bottle; all orders that tie for it are kept
+    while frontier.first().is_some_and(|partial| !partial.remaining.is_empty()) {
+        let mut best: Option<[u8; 7]> = None;
+        let mut next = HashSet::new();
+        for partial in &frontier {
+            for (i, bottle) in partial.remaining.iter().enumerate() {
+                let (encoded, labels) = partial.encode(bottle);
+                match best {
+                    Some(best) if encoded > best => continue,
+                    Some(best) if encoded == best => {}
+                    _ => {
+                        best = Some(encoded);
+                        next.clear();
+                    }
+                }
+                let mut remaining = partial.remaining.clone();
+                remaining.remove(i);
+                next.insert(Partial { remaining, labels });
+            }
+        }
+        form.extend(best.unwrap());
+        frontier = next.into_iter().collect();
+    }
+    form
+}
+
+pub fn is_isomorphic(a: &WaterSorting, b: &WaterSorting) -> bool {
+    a.bottles.len() == b.bottles.len() && canonical_form(a) == canonical_form(b)
+}
+
+#[wasm_bindgen]
+impl WaterSorting {
+    /// Same puzzle as `other` up to the order of bottles and the naming of colors.
+    pub fn is_isomorphic_to(&self, other: &WaterSorting) -> bool {
+        is_isomorphic(self, other)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelRef {
+    // index into the packs given to `find_duplicates`
+    pub pack: usize,
+    pub level: usize,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateReport {
+    pub levels_checked: usize,
+    // levels that are the same puzzle, in pack order, each group holds at least two levels
+    pub groups: Vec<Vec<LevelRef>>,
+    pack_names: Vec<String>,
+}
+
+impl DuplicateReport {
+    pub fn duplicates(&self) -> usize {
+        self.groups.iter().map(|group| group.len() - 1).sum()
+    }
+}
+
+impl Display for DuplicateReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} levels checked, {} duplicates in {} groups", self.levels_checked, self.duplicates(), self.groups.len())?;
+        for group in &self.groups {
+            let levels = group.iter().map(|l| format!("{}/{}", self.pack_names[l.pack], l.id)).collect::<Vec<_>>();
+            writeln!(f, "  {}", levels.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Groups the levels of all `packs` that are the same puzzle up to bottle order and color names.
+/// Levels that cannot be built are skipped.
+pub fn find_duplicates(packs: &[LevelPack]) -> DuplicateReport {
+    let mut groups: Vec<Vec<LevelRef>> = Vec::new();
+    let mut by_form: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut levels_checked = 0;
+    for (pack_index, pack) in packs.iter().enumerate() {
+        for (level_index, level) in pack.levels.iter().enumerate() {
+            let Ok(built) = level.build() else { continue };
+            levels_checked += 1;
+            let reference = LevelRef { pack: pack_index, level: level_index, id: level.id.clone() };
+            let group = *by_form.entry(canonical_form(&built)).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].push(reference);
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    DuplicateReport { levels_checked, groups, pack_names: packs.iter().map(|pack| pack.name.clone()).collect() }
+}
+
+#[cfg(test)]
+mod duplicates_tests {
+    use crate::{Color, WaterSorting};
+    use crate::duplicates::{canonical_form, find_duplicates, is_isomorphic, LevelRef};
+    use crate::pack::{LevelPack, PackLevel};
+    use crate::rules::RuleSet;
+
+    fn level(bottles: &[[Color; 4]], empty: usize) -> WaterSorting {
+        let mut w = WaterSorting::new();
+        bottles.iter().for_each(|[b, l1, l2, t]| w.init_bottle_with_four_colors(*b, *l1, *l2, *t));
+        (0..empty).for_each(|_| w.init_empty_bottle());
+        w
+    }
+
+    fn original() -> WaterSorting {
+        level(&[
+            [Color::Red, Color::Blue, Color::Green, Color::Red],
+            [Color::Green, Color::Green, Color::Blue, Color::Red],
+            [Color::Blue, Color::Red, Color::Blue, Color::Green],
+        ], 2)
+    }
+
+    #[test]
+    fn renamed_colors_are_the_same_level() {
+        let renamed = level(&[
+            [Color::Teal, Color::Olive, Color::Peach, Color::Teal],
+            [Color::Peach, Color::Peach, Color::Olive, Color::Teal],
+            [Color::Olive, Color::Teal, Color::Olive, Color::Peach],
+        ], 2);
+
+        assert!(is_isomorphic(&original(), &renamed));
+        assert!(original().is_isomorphic_to(&renamed));
+    }
+
+    #[test]
+    fn reordered_bottles_with_renamed_colors_are_the_same_level() {
+        let mut shuffled = WaterSorting::new();
+        shuffled.init_empty_bottle();
+        shuffled.init_bottle_with_four_colors(Color::Yellow, Color::Red, Color::Yellow, Color::Gray);
+        shuffled.init_bottle_with_four_colors(Color::Gray, Color::Gray, Color::Yellow, Color::Red);
+        shuffled.init_empty_bottle();
+        shuffled.init_bottle_with_four_colors(Color::Red, Color::Yellow, Color::Gray, Color::Red);
+
+        assert_eq!(canonical_form(&original()), canonical_form(&shuffled));
+    }
+
+    #[test]
+    fn different_puzzles_are_told_apart() {
+        let swapped_layers = level(&[
+            [Color::Blue, Color::Red, Color::Green, Color::Red],
+            [Color::Green, Color::Green, Color::Blue, Color::Red],
+            [Color::Blue, Color::Red, Color::Blue, Color::Green],
+        ], 2);
+        let fewer_empty = level(&[
+            [Color::Red, Color::Blue, Color::Green, Color::Red],
+            [Color::Green, Color::Green, Color::Blue, Color::Red],
+            [Color::Blue, Color::Red, Color::Blue, Color::Green],
+        ], 1);
+
+        assert!(!is_isomorphic(&original(), &swapped_layers));
+        assert!(!is_isomorphic(&original(), &fewer_empty));
+    }
+
+    #[test]
+    fn bottle_capacity_is_part_of_the_form() {
+        let mut small = WaterSorting::new();
+        small.init_bottle_with_colors(3, &[Color::Red, Color::Blue]);
+        let mut large = WaterSorting::new();
+        large.init_bottle_with_colors(4, &[Color::Red, Color::Blue]);
+
+        assert!(!is_isomorphic(&small, &large));
+    }
+
+    #[test]
+    fn symmetric_level_keeps_a_small_form() {
+        let symmetric = level(&[
+            [Color::Red, Color::Blue, Color::Red, Color::Blue],
+            [Color::Blue, Color::Red, Color::Blue, Color::Red],
+            [Color::Green, Color::Gray, Color::Green, Color::Gray],
+            [Color::Gray, Color::Green, Color::Gray, Color::Green],
+        ], 2);
+
+        assert_eq!(canonical_form(&symmetric).len(), RuleSet::water().to_string().len() + 6 * 7);
+    }
+
+    #[test]
+    fn rainbow_is_not_renamed_like_a_color() {
+        let rainbow = level(&[[Color::Red, Color::Rainbow, Color::Red, Color::Red]], 1);
+        let teal = level(&[[Color::Red, Color::Teal, Color::Red, Color::Red]], 1);
+
+        assert!(!is_isomorphic(&rainbow, &teal));
+        assert!(is_isomorphic(&rainbow, &level(&[[Color::Blue, Color::Rainbow, Color::Blue, Color::Blue]], 1)));
+    }
+
+    #[test]
+    fn color_locks_are_renamed_with_the_colors() {
+        let mut locked = original();
+        locked.lock_bottle_to_color(3, Color::Red);
+        let mut renamed = level(&[
+            [Color::Teal, Color::Blue, Color::Green, Color::Teal],
+            [Color::Green, Color::Green, Color::Blue, Color::Teal],
+            [Color::Blue, Color::Teal, Color::Blue, Color::Green],
+        ], 2);
+        renamed.lock_bottle_to_color(4, Color::Teal);
+
+        assert!(!is_isomorphic(&locked, &original()));
+        assert!(is_isomorphic(&locked, &renamed));
+    }
+
+    #[test]
+    fn same_layout_under_other_rules_is_a_different_level() {
+        let mut ball_sort = WaterSorting::with_rules(RuleSet::ball_sort());
+        original().bottles.iter().for_each(|b| ball_sort.init_bottle_with_colors(4, &b.layers().iter().flatten().copied().collect::<Vec<_>>()));
+
+        assert!(!is_isomorphic(&original(), &ball_sort));
+    }
+
+    #[test]
+    fn duplicates_are_grouped_across_packs() {
+        let mut first = LevelPack::new("First", "a");
+        first.levels.push(PackLevel::new("a1", "A1", &original()));
+        first.levels.push(PackLevel::new("a2", "A2", &level(&[[Color::Red, Color::Red, Color::Red, Color::Red]], 1)));
+        let mut second = LevelPack::new("Second", "b");
+        let mut reversed = original();
+        reversed.bottles.reverse();
+        second.levels.push(PackLevel::new("b1", "B1", &reversed));
+        second.levels.push(PackLevel::new("b2", "B2", &level(&[[Color::Blue, Color::Blue, Color::Blue, Color::Blue]], 1)));
+        second.levels.push(PackLevel::new("b3", "B3", &level(&[[Color::Blue, Color::Blue, Color::Blue, Color::Blue]], 2)));
+
+        let report = find_duplicates(&[first, second]);
+
+        assert_eq!(report.levels_checked, 5);
+        assert_eq!(report.duplicates(), 2);
+        assert_eq!(report.groups[0], [
+            LevelRef { pack: 0, level: 0, id: "a1".to_string() },
+            LevelRef { pack: 1, level: 0, id: "b1".to_string() },
+        ]);
+        assert_eq!(report.groups[1].iter().map(|l| l.id.as_str()).collect::<Vec<_>>(), ["a2", "b2"]);
+        assert!(report.to_string().contains("First/a1, Second/b1"));
+    }
+}
@@ -0,0 +1,243 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use crate::{Color, WaterSorting};
+use crate::analysis::{rate_difficulty, DifficultyRating};
+use crate::generator::{build_level, layout_violations, mutated_layout, needs_required_empty_bottles, shuffled_layout, validate, GeneratorError, GeneratorParams};
+use crate::rng::Rng;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EvolutionParams {
+    // shape of the levels, seed, solver budget and constraints; the difficulty band is ignored
+    pub generator: GeneratorParams,
+    pub population: usize,
+    pub generations: usize,
+    // fitness added by a dead-end ratio of 1, on top of one point per pour of the optimal solution
+    pub dead_end_weight: f64,
+    // individuals kept in the hall of fame
+    pub hall_of_fame: usize,
+}
+
+impl EvolutionParams {
+    pub fn new(generator: GeneratorParams, population: usize, generations: usize) -> Self {
+        EvolutionParams { generator, population, generations, dead_end_weight: 10.0, hall_of_fame: 5 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Individual {
+    // position in `Evolution::individuals`
+    pub id: usize,
+    // the individual this one was mutated from, `None` for the initial population
+    pub parent: Option<usize>,
+    pub generation: usize,
+    pub level: WaterSorting,
+    pub rating: DifficultyRating,
+    pub fitness: f64,
+    layout: Vec<Vec<Color>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GenerationSummary {
+    pub generation: usize,
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+    // solvable children that made it into the archive
+    pub accepted: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evolution {
+    // every solvable individual ever created, so the lineage of any of them can be traced back
+    pub individuals: Vec<Individual>,
+    pub generations: Vec<GenerationSummary>,
+    // ids of the fittest individuals, best first
+    pub hall_of_fame: Vec<usize>,
+}
+
+impl Evolution {
+    pub fn best(&self) -> &Individual {
+        &self.individuals[self.hall_of_fame[0]]
+    }
+
+    /// Ancestors of the individual with `id`, from the initial population down to the individual itself.
+    pub fn lineage(&self, id: usize) -> Vec<&Individual> {
+        let mut lineage = vec![&self.individuals[id]];
+        while let Some(parent) = lineage.last().unwrap().parent {
+            lineage.push(&self.individuals[parent]);
+        }
+        lineage.reverse();
+        lineage
+    }
+}
+
+fn fitness(rating: &DifficultyRating, dead_end_weight: f64) -> f64 {
+    rating.optimal_length as f64 + rating.dead_end_ratio * dead_end_weight
+}
+
+fn by_fitness(a: &Individual, b: &Individual) -> Ordering {
+    b.fitness.total_cmp(&a.fitness).then(a.id.cmp(&b.id))
+}
+
+/// Evolves levels towards the longest optimal solutions with the most dead ends. Each generation
+/// mutates parents picked by tournament (two layers swapped between bottles), keeps only children
+/// proven solvable and carries the fittest of parents and children over. The same parameters always
+/// give the same result.
+pub fn evolve(params: &EvolutionParams) -> Result<Evolution, GeneratorError> {
+    let generator = &params.generator;
+    validate(generator)?;
+    if params.population == 0 || params.hall_of_fame == 0 {
+        return Err(GeneratorError::InvalidParams("population and hall of fame must not be empty".to_string()));
+    }
+    let mut rng = Rng::new(generator.seed);
+    let mut evolution = Evolution { individuals: Vec::new(), generations: Vec::new(), hall_of_fame: Vec::new() };
+    let mut seen = HashSet::new();
+
+    let mut attempts = 0;
+    while evolution.individuals.len() < params.population && attempts < generator.max_attempts {
+        attempts += 1;
+        let layout = shuffled_layout(generator, &mut rng);
+        admit(params, &mut evolution, &mut seen, layout, None, 0);
+    }
+    if evolution.individuals.is_empty() {
+        return Err(GeneratorError::NoSolvableLevel { attempts });
+    }
+    let mut population = (0..evolution.individuals.len()).collect::<Vec<_>>();
+
+    for generation in 1..=params.generations {
+        let mut children = Vec::new();
+        for _ in 0..params.population {
+            let (a, b) = (population[rng.below(population.len())], population[rng.below(population.len())]);
+            let parent = if by_fitness(&evolution.individuals[a], &evolution.individuals[b]) == Ordering::Greater { b } else { a };
+            let layout = mutated_layout(&evolution.individuals[parent].layout, &mut rng);
+            if let Some(child) = admit(params, &mut evolution, &mut seen, layout, Some(parent), generation) {
+                children.push(child);
+            }
+        }
+        let accepted = children.len();
+        population.extend(children);
+        population.sort_by(|&a, &b| by_fitness(&evolution.individuals[a], &evolution.individuals[b]));
+        population.truncate(params.population);
+        let fitness = population.iter().map(|&id| evolution.individuals[id].fitness).collect::<Vec<_>>();
+        evolution.generations.push(GenerationSummary {
+            generation,
+            best_fitness: fitness[0],
+            mean_fitness: fitness.iter().sum::<f64>() / fitness.len() as f64,
+            accepted,
+        });
+    }
+
+    let mut ranked = evolution.individuals.iter().collect::<Vec<_>>();
+    ranked.sort_by(|a, b| by_fitness(a, b));
+    evolution.hall_of_fame = ranked.iter().take(params.hall_of_fame).map(|individual| individual.id).collect();
+    Ok(evolution)
+}
+
+// rates a new layout and archives it when it meets the constraints and is solvable
+fn admit(
+    params: &EvolutionParams,
+    evolution: &mut Evolution,
+    seen: &mut HashSet<Vec<Vec<Color>>>,
+    layout: Vec<Vec<Color>>,
+    parent: Option<usize>,
+    generation: usize,
+) -> Option<usize> {
+    if !layout_violations(&params.generator.constraints, &layout).is_empty() || !seen.insert(layout.clone()) {
+        return None;
+    }
+    let level = build_level(&params.generator, &layout);
+    if level.win() || !needs_required_empty_bottles(&params.generator, &layout) {
+        return None;
+    }
+    let rating = rate_difficulty(&level, params.generator.max_nodes);
+    if !rating.solvable {
+        return None;
+    }
+    let id = evolution.individuals.len();
+    let fitness = fitness(&rating, params.dead_end_weight);
+    evolution.individuals.push(Individual { id, parent, generation, level, rating, fitness, layout });
+    Some(id)
+}
+
+#[cfg(test)]
+mod evolution_tests {
+    use crate::analysis::minimum_empty_bottles;
+    use crate::evolution::{evolve, EvolutionParams};
+    use crate::generator::{GenerationConstraints, GeneratorError, GeneratorParams};
+
+    fn params(seed: u64) -> EvolutionParams {
+        EvolutionParams::new(GeneratorParams::new(3, 2, 4, seed), 4, 5)
+    }
+
+    #[test]
+    fn same_seed_evolves_the_same_levels() {
+        let first = evolve(&params(11)).unwrap();
+        let second = evolve(&params(11)).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn best_fitness_never_decreases() {
+        let evolution = evolve(&params(5)).unwrap();
+
+        assert_eq!(evolution.generations.len(), 5);
+        assert!(evolution.generations.windows(2).all(|w| w[0].best_fitness <= w[1].best_fitness));
+        let initial_best = evolution.individuals.iter().filter(|i| i.generation == 0).map(|i| i.fitness).fold(0.0, f64::max);
+        assert!(evolution.best().fitness >= initial_best);
+    }
+
+    #[test]
+    fn every_individual_is_solvable() {
+        let evolution = evolve(&params(3)).unwrap();
+
+        assert!(evolution.individuals.iter().all(|i| i.rating.solvable && !i.level.win()));
+        assert!(evolution.individuals.iter().all(|i| i.fitness == i.rating.optimal_length as f64 + i.rating.dead_end_ratio * 10.0));
+    }
+
+    #[test]
+    fn every_individual_needs_the_required_empty_bottles() {
+        let constraints = GenerationConstraints { required_empty_bottles: Some(2), ..GenerationConstraints::new() };
+        let params = EvolutionParams::new(GeneratorParams::new(3, 2, 4, 4).with_constraints(constraints), 3, 2);
+
+        let evolution = evolve(&params).unwrap();
+
+        for individual in &evolution.individuals {
+            assert_eq!(minimum_empty_bottles(&individual.level, 2, usize::MAX).unwrap().empty_bottles, 2);
+        }
+    }
+
+    #[test]
+    fn lineage_leads_back_to_the_initial_population() {
+        let evolution = evolve(&params(7)).unwrap();
+
+        for &id in &evolution.hall_of_fame {
+            let lineage = evolution.lineage(id);
+            assert_eq!(lineage.first().unwrap().parent, None);
+            assert_eq!(lineage.last().unwrap().id, id);
+            assert!(lineage.windows(2).all(|w| w[1].parent == Some(w[0].id) && w[0].generation < w[1].generation));
+        }
+    }
+
+    #[test]
+    fn hall_of_fame_is_ordered_by_fitness() {
+        let evolution = evolve(&params(9)).unwrap();
+
+        let fitness = evolution.hall_of_fame.iter().map(|&id| evolution.individuals[id].fitness).collect::<Vec<_>>();
+        assert_eq!(fitness.len(), 5);
+        assert!(fitness.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn empty_population_is_rejected() {
+        let params = EvolutionParams { population: 0, ..params(1) };
+
+        assert!(matches!(evolve(&params), Err(GeneratorError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn reports_failure_when_no_initial_level_is_solvable() {
+        let params = EvolutionParams::new(GeneratorParams { max_attempts: 3, ..GeneratorParams::new(4, 0, 4, 1) }, 4, 2);
+
+        assert_eq!(evolve(&params), Err(GeneratorError::NoSolvableLevel { attempts: 3 }));
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+use crate::{WaterSorting, MAX_CAPACITY};
+
+/// When the frozen layers of a level thaw, all of them at once.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum ThawTrigger {
+    // a pour completes a bottle
+    #[default]
+    BottleCompleted,
+    // the given number of moves has been made
+    MovesMade(usize),
+}
+
+impl Display for ThawTrigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThawTrigger::BottleCompleted => write!(f, "completed"),
+            ThawTrigger::MovesMade(moves) => write!(f, "after {}", moves),
+        }
+    }
+}
+
+impl FromStr for ThawTrigger {
+    type Err = String;
+
+    // `completed` or `after N` (moves)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["completed"] => Ok(ThawTrigger::BottleCompleted),
+            ["after", moves] => moves.parse().map(ThawTrigger::MovesMade).map_err(|_| format!("`{}` is not a number of moves", moves)),
+            _ => Err(format!("unknown thaw trigger `{}`, expected `completed` or `after N`", s.trim())),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WaterSorting {
+    /// Freezes `layer` (0 is the bottom) of bottle `index`: it cannot be poured out until the
+    /// thaw trigger fires. False when the bottle has no such layer.
+    pub fn freeze_layer(&mut self, index: usize, layer: usize) -> bool {
+        match self.bottles.get_mut(index) {
+            Some(bottle) if bottle.layers().get(layer).is_some_and(Option::is_some) => {
+                bottle.frozen[layer] = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_layer_frozen(&self, index: usize, layer: usize) -> bool {
+        self.bottles.get(index).is_some_and(|bottle| bottle.frozen.get(layer) == Some(&true))
+    }
+
+    pub fn thaw_when_bottle_completed(&mut self) {
+        self.thaw_trigger = ThawTrigger::BottleCompleted;
+    }
+
+    pub fn thaw_after_moves(&mut self, moves: usize) {
+        self.thaw_trigger = ThawTrigger::MovesMade(moves);
+    }
+
+    /// One byte per bottle with a bit for every frozen layer, bottom first.
+    pub fn frozen_layers(&mut self) -> *const u8 {
+        self.frozen_serialized = self.bottles.iter().map(|b| b.frozen_mask()).collect();
+        self.frozen_serialized.as_ptr()
+    }
+}
+
+impl WaterSorting {
+    pub fn thaw_trigger(&self) -> ThawTrigger {
+        self.thaw_trigger
+    }
+
+    pub fn set_thaw_trigger(&mut self, trigger: ThawTrigger) {
+        self.thaw_trigger = trigger;
+    }
+
+    pub(crate) fn has_frozen_layers(&self) -> bool {
+        self.bottles.iter().any(|b| b.frozen_mask() != 0)
+    }
+
+    // moves left until the layers thaw; part of the state, since it decides which pours come later
+    pub(crate) fn thaw_countdown(&self) -> Option<usize> {
+        match self.thaw_trigger {
+            ThawTrigger::MovesMade(moves) if self.has_frozen_layers() => Some(moves.saturating_sub(self.moves)),
+            _ => None,
+        }
+    }
+
+    // called after every pour into `to`
+    pub(crate) fn thaw_if_triggered(&mut self, to: usize) {
+        let fired = match self.thaw_trigger {
+            ThawTrigger::BottleCompleted => self.bottles[to].is_solved(),
+            ThawTrigger::MovesMade(moves) => self.moves >= moves,
+        };
+        if fired {
+            self.bottles.iter_mut().for_each(|b| b.frozen = [false; MAX_CAPACITY]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod frozen_tests {
+    use crate::{Color, WaterSolver, WaterSorting};
+    use crate::frozen::ThawTrigger;
+    use crate::rules::PourRejection;
+    use crate::strategy::PruningRules;
+
+    // the red on top of the first bottle is frozen until the blue bottle is completed
+    fn level() -> WaterSorting {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Blue, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Blue);
+        w.init_empty_bottle();
+        w.init_empty_bottle();
+        w.freeze_layer(0, 3);
+        w
+    }
+
+    #[test]
+    fn frozen_layer_cannot_be_poured_out() {
+        let mut w = level();
+
+        assert_eq!(w.try_pour(0, 2), Err(PourRejection::Frozen));
+        assert_eq!(w.bottles[0].amount_to_pour(), 0);
+        assert!(w.possible_moves(&PruningRules::none()).iter().all(|p| p.from != 0));
+    }
+
+    #[test]
+    fn missing_layers_cannot_be_frozen() {
+        let mut w = level();
+
+        assert!(!w.freeze_layer(2, 0));
+        assert!(!w.freeze_layer(0, 7));
+        assert!(!w.freeze_layer(9, 0));
+        assert!(!w.is_layer_frozen(9, 0));
+        assert!(w.freeze_layer(1, 0));
+    }
+
+    #[test]
+    fn empty_bottle_does_not_help_while_the_only_run_is_frozen() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Blue, Color::Red);
+        w.init_empty_bottle();
+        w.freeze_layer(0, 3);
+
+        assert!(!w.move_available());
+    }
+
+    #[test]
+    fn completing_a_bottle_thaws_the_level() {
+        let mut w = level();
+        w.init_bottle_with_three_colors(Color::Green, Color::Green, Color::Green);
+        w.init_bottle_with_one_color(Color::Green);
+
+        w.pour(1, 2);
+        assert!(w.is_layer_frozen(0, 3));
+        w.pour(5, 4);
+
+        assert!(!w.is_layer_frozen(0, 3));
+        assert_eq!(w.try_pour(0, 1), Ok(1));
+    }
+
+    #[test]
+    fn layers_thaw_after_the_given_number_of_moves_and_freeze_again_on_undo() {
+        let mut w = level();
+        w.thaw_after_moves(2);
+
+        w.pour(1, 2);
+        assert_eq!(w.thaw_countdown(), Some(1));
+        w.pour(2, 3);
+        assert!(!w.is_layer_frozen(0, 3));
+        w.undo();
+
+        assert!(w.is_layer_frozen(0, 3));
+    }
+
+    #[test]
+    fn states_waiting_for_a_thaw_differ_by_the_moves_left() {
+        let mut w = level();
+        w.thaw_after_moves(4);
+        let mut sooner = w.clone();
+        let mut later = w.clone();
+
+        sooner.pour(1, 3);
+        later.pour(1, 2);
+        later.pour(2, 3);
+
+        assert_eq!(later.bottles, sooner.bottles);
+        assert_ne!(later, sooner);
+    }
+
+    #[test]
+    fn solver_waits_for_the_thaw() {
+        let mut w = level();
+        w.thaw_after_moves(3);
+
+        let solution = WaterSolver::new(&w).solve_configured().0;
+
+        assert!(!solution.is_empty());
+        solution.iter().for_each(|p| assert!(w.try_pour(p.from, p.to).is_ok()));
+        assert!(w.win());
+    }
+
+    #[test]
+    fn thaw_trigger_round_trips_through_text() {
+        assert_eq!("after 5".parse(), Ok(ThawTrigger::MovesMade(5)));
+        assert_eq!(ThawTrigger::MovesMade(5).to_string().parse(), Ok(ThawTrigger::MovesMade(5)));
+        assert_eq!(ThawTrigger::BottleCompleted.to_string(), "completed");
+        assert!("later".parse::<ThawTrigger>().is_err());
+    }
+}
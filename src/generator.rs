@@ -0,0 +1,454 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use wasm_bindgen::prelude::*;
+use crate::{Color, Pour, WaterSolver, WaterSorting, COLORS, MAX_CAPACITY};
+use crate::analysis::{rate_difficulty, DifficultyRating};
+use crate::proof::explore;
+use crate::rng::Rng;
+use crate::strategy::{Algorithm, SolverConfig};
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GeneratorParams {
+    pub colors: usize,
+    pub empty_bottles: usize,
+    pub capacity: usize,
+    pub seed: u64,
+    // candidates tried before giving up
+    pub max_attempts: usize,
+    // solver budget spent on verifying (and rating) a single candidate
+    pub max_nodes: usize,
+    // accepted band of `rate_difficulty` scores, 0..100 accepts any level
+    pub min_difficulty: f64,
+    pub max_difficulty: f64,
+    pub constraints: GenerationConstraints,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GenerationConstraints {
+    // no filled bottle may start out solved
+    pub no_solved_bottles: bool,
+    // longest allowed run of one color inside a bottle
+    pub max_run_length: usize,
+    // fewest distinct colors every filled bottle has to hold
+    pub min_distinct_colors: usize,
+    // the level must not be solvable with fewer empty bottles than this
+    pub required_empty_bottles: Option<usize>,
+}
+
+#[wasm_bindgen]
+impl GenerationConstraints {
+    pub fn new() -> Self {
+        GenerationConstraints {
+            no_solved_bottles: false,
+            max_run_length: MAX_CAPACITY,
+            min_distinct_colors: 1,
+            required_empty_bottles: None,
+        }
+    }
+}
+
+impl Default for GenerationConstraints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConstraintViolation {
+    SolvedBottle,
+    RunTooLong,
+    TooFewDistinctColors,
+    EmptyBottlesNotRequired,
+}
+
+impl Display for ConstraintViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintViolation::SolvedBottle => write!(f, "a bottle is already solved"),
+            ConstraintViolation::RunTooLong => write!(f, "a run of one color is too long"),
+            ConstraintViolation::TooFewDistinctColors => write!(f, "a bottle has too few distinct colors"),
+            ConstraintViolation::EmptyBottlesNotRequired => write!(f, "the level is solvable with fewer empty bottles"),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl GeneratorParams {
+    pub fn new(colors: usize, empty_bottles: usize, capacity: usize, seed: u64) -> Self {
+        GeneratorParams {
+            colors,
+            empty_bottles,
+            capacity,
+            seed,
+            max_attempts: 100,
+            max_nodes: 100_000,
+            min_difficulty: 0.0,
+            max_difficulty: 100.0,
+            constraints: GenerationConstraints::new(),
+        }
+    }
+
+    pub fn with_difficulty(self, min_difficulty: f64, max_difficulty: f64) -> Self {
+        GeneratorParams { min_difficulty, max_difficulty, ..self }
+    }
+
+    pub fn with_constraints(self, constraints: GenerationConstraints) -> Self {
+        GeneratorParams { constraints, ..self }
+    }
+}
+
+impl GeneratorParams {
+    fn targets_difficulty(&self) -> bool {
+        self.min_difficulty > 0.0 || self.max_difficulty < 100.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneratorError {
+    InvalidParams(String),
+    NoSolvableLevel { attempts: usize },
+    OutsideDifficultyBand { attempts: usize, closest_score: f64 },
+    // constraints no layout can meet
+    ImpossibleConstraints(Vec<ConstraintViolation>),
+    // how many candidates broke each constraint
+    ConstraintsNotMet { attempts: usize, violations: Vec<(ConstraintViolation, usize)> },
+}
+
+impl Display for GeneratorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneratorError::InvalidParams(reason) => write!(f, "invalid generator parameters: {}", reason),
+            GeneratorError::NoSolvableLevel { attempts } => write!(f, "no solvable level found in {} attempts", attempts),
+            GeneratorError::OutsideDifficultyBand { attempts, closest_score } =>
+                write!(f, "no level in the difficulty band found in {} attempts, closest scored {:.1}", attempts, closest_score),
+            GeneratorError::ImpossibleConstraints(violations) => {
+                write!(f, "constraints cannot be met:")?;
+                violations.iter().try_for_each(|v| write!(f, " {};", v))
+            }
+            GeneratorError::ConstraintsNotMet { attempts, violations } => {
+                write!(f, "no level meeting the constraints found in {} attempts:", attempts)?;
+                violations.iter().try_for_each(|(v, count)| write!(f, " {} ({}x);", v, count))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedLevel {
+    pub level: WaterSorting,
+    // a solution found while verifying the level, not necessarily the shortest one
+    pub solution: Vec<Pour>,
+    // candidates tried, including the returned one
+    pub attempts: usize,
+    // only rated when a difficulty band was requested
+    pub rating: Option<DifficultyRating>,
+}
+
+/// Builds a random level from `params`. Candidates are shuffled from the seed and kept only when the
+/// solver proves them solvable, so the same parameters always give the same level.
+///
+/// With a difficulty band, solvable candidates are also rated. Most following candidates are then
+/// mutations (two layers swapped) of the one closest to the band so far, the rest are fresh shuffles.
+pub fn generate(params: &GeneratorParams) -> Result<GeneratedLevel, GeneratorError> {
+    validate(params)?;
+    let mut rng = Rng::new(params.seed);
+    // distance to the band, score and layout of the closest candidate
+    let mut closest: Option<(f64, f64, Vec<Vec<Color>>)> = None;
+    let mut violations: Vec<(ConstraintViolation, usize)> = Vec::new();
+    for attempt in 1..=params.max_attempts {
+        let layout = match &closest {
+            Some((_, _, best)) if rng.below(4) != 0 => mutated_layout(best, &mut rng),
+            _ => shuffled_layout(params, &mut rng),
+        };
+        let mut broken = layout_violations(&params.constraints, &layout);
+        let level = build_level(params, &layout);
+        let solution = if broken.is_empty() { verify(&level, params.max_nodes) } else { None };
+        if solution.is_some() && !needs_required_empty_bottles(params, &layout) {
+            broken.push(ConstraintViolation::EmptyBottlesNotRequired);
+        }
+        if !broken.is_empty() {
+            for violation in broken {
+                match violations.iter_mut().find(|(v, _)| *v == violation) {
+                    Some((_, count)) => *count += 1,
+                    None => violations.push((violation, 1)),
+                }
+            }
+            continue;
+        }
+        let Some(solution) = solution else { continue };
+        if !params.targets_difficulty() {
+            return Ok(GeneratedLevel { level, solution, attempts: attempt, rating: None });
+        }
+        let rating = rate_difficulty(&level, params.max_nodes);
+        let distance = (params.min_difficulty - rating.score).max(rating.score - params.max_difficulty).max(0.0);
+        if distance == 0.0 {
+            return Ok(GeneratedLevel { level, solution, attempts: attempt, rating: Some(rating) });
+        }
+        if closest.as_ref().map_or(true, |(closest_distance, _, _)| distance < *closest_distance) {
+            closest = Some((distance, rating.score, layout));
+        }
+    }
+    match closest {
+        Some((_, closest_score, _)) => Err(GeneratorError::OutsideDifficultyBand { attempts: params.max_attempts, closest_score }),
+        None if !violations.is_empty() => Err(GeneratorError::ConstraintsNotMet { attempts: params.max_attempts, violations }),
+        None => Err(GeneratorError::NoSolvableLevel { attempts: params.max_attempts }),
+    }
+}
+
+#[wasm_bindgen]
+pub fn generate_level(params: &GeneratorParams) -> Option<WaterSorting> {
+    generate(params).ok().map(|generated| generated.level)
+}
+
+pub(crate) fn validate(params: &GeneratorParams) -> Result<(), GeneratorError> {
+    if params.colors == 0 || params.colors > COLORS.len() {
+        return Err(GeneratorError::InvalidParams(format!("colors must be between 1 and {}", COLORS.len())));
+    }
+    if params.capacity == 0 || params.capacity > MAX_CAPACITY {
+        return Err(GeneratorError::InvalidParams(format!("capacity must be between 1 and {}", MAX_CAPACITY)));
+    }
+    if !(0.0..=100.0).contains(&params.min_difficulty) || params.min_difficulty > params.max_difficulty {
+        return Err(GeneratorError::InvalidParams("difficulty band must be a range within 0..100".to_string()));
+    }
+    let constraints = &params.constraints;
+    let mut impossible = Vec::new();
+    if constraints.no_solved_bottles && params.colors == 1 {
+        impossible.push(ConstraintViolation::SolvedBottle);
+    }
+    // a bottle can only avoid long runs if there are enough other colors to break them up
+    if constraints.max_run_length == 0 || (constraints.max_run_length < params.capacity && params.colors == 1) {
+        impossible.push(ConstraintViolation::RunTooLong);
+    }
+    if constraints.min_distinct_colors > params.capacity.min(params.colors) {
+        impossible.push(ConstraintViolation::TooFewDistinctColors);
+    }
+    if constraints.required_empty_bottles.is_some_and(|required| required > params.empty_bottles) {
+        impossible.push(ConstraintViolation::EmptyBottlesNotRequired);
+    }
+    if !impossible.is_empty() {
+        return Err(GeneratorError::ImpossibleConstraints(impossible));
+    }
+    Ok(())
+}
+
+pub(crate) fn layout_violations(constraints: &GenerationConstraints, layout: &[Vec<Color>]) -> Vec<ConstraintViolation> {
+    let mut violations = Vec::new();
+    let solved = |colors: &Vec<Color>| colors.iter().all(|&c| c == colors[0]);
+    if constraints.no_solved_bottles && layout.iter().any(solved) {
+        violations.push(ConstraintViolation::SolvedBottle);
+    }
+    let longest_run = |colors: &Vec<Color>| {
+        colors.chunk_by(|a, b| a == b).map(|run| run.len()).max().unwrap_or(0)
+    };
+    if layout.iter().any(|colors| longest_run(colors) > constraints.max_run_length) {
+        violations.push(ConstraintViolation::RunTooLong);
+    }
+    let distinct = |colors: &Vec<Color>| colors.iter().collect::<HashSet<_>>().len();
+    if layout.iter().any(|colors| distinct(colors) < constraints.min_distinct_colors) {
+        violations.push(ConstraintViolation::TooFewDistinctColors);
+    }
+    violations
+}
+
+// a solvable layout with one empty bottle less than required must be proven unsolvable
+pub(crate) fn needs_required_empty_bottles(params: &GeneratorParams, layout: &[Vec<Color>]) -> bool {
+    match params.constraints.required_empty_bottles {
+        None | Some(0) => true,
+        Some(required) => {
+            let fewer = GeneratorParams { empty_bottles: required - 1, ..*params };
+            explore(&build_level(&fewer, layout), params.max_nodes, false).is_unsolvable()
+        }
+    }
+}
+
+// layers of the filled bottles, bottom first
+pub(crate) fn shuffled_layout(params: &GeneratorParams, rng: &mut Rng) -> Vec<Vec<Color>> {
+    let mut layers = COLORS[..params.colors]
+        .iter()
+        .flat_map(|&c| [c; MAX_CAPACITY].into_iter().take(params.capacity))
+        .collect::<Vec<Color>>();
+    rng.shuffle(&mut layers);
+    layers.chunks(params.capacity).map(|colors| colors.to_vec()).collect()
+}
+
+pub(crate) fn mutated_layout(layout: &[Vec<Color>], rng: &mut Rng) -> Vec<Vec<Color>> {
+    let mut layout = layout.to_vec();
+    if layout.len() < 2 {
+        return layout;
+    }
+    let first = rng.below(layout.len());
+    let second = (first + 1 + rng.below(layout.len() - 1)) % layout.len();
+    let (i, j) = (rng.below(layout[first].len()), rng.below(layout[second].len()));
+    let swapped = layout[first][i];
+    layout[first][i] = layout[second][j];
+    layout[second][j] = swapped;
+    layout
+}
+
+pub(crate) fn build_level(params: &GeneratorParams, layout: &[Vec<Color>]) -> WaterSorting {
+    let mut level = WaterSorting::new();
+    layout.iter().for_each(|colors| level.init_bottle_with_colors(params.capacity, colors));
+    (0..params.empty_bottles).for_each(|_| level.init_empty_bottle_with_capacity(params.capacity));
+    level
+}
+
+// an already won level does not count as a puzzle
+pub(crate) fn verify(level: &WaterSorting, max_nodes: usize) -> Option<Vec<Pour>> {
+    if level.win() {
+        return None;
+    }
+    let config = SolverConfig { max_nodes, max_depth: usize::MAX, ..SolverConfig::with_algorithm(Algorithm::AStar) };
+    let solution = WaterSolver::with_config(level, config).solve_configured().0;
+    if solution.is_empty() { None } else { Some(solution) }
+}
+
+#[cfg(test)]
+mod generator_tests {
+    use crate::Color;
+    use crate::analysis::minimum_empty_bottles;
+    use crate::generator::{generate, layout_violations, ConstraintViolation, GenerationConstraints, GeneratorError, GeneratorParams};
+
+    #[test]
+    fn same_seed_generates_the_same_level() {
+        let params = GeneratorParams::new(4, 2, 4, 2023);
+
+        let first = generate(&params).unwrap();
+        let second = generate(&params).unwrap();
+
+        assert_eq!(first.level, second.level);
+        assert_eq!(first.solution, second.solution);
+    }
+
+    #[test]
+    fn different_seeds_generate_different_levels() {
+        let first = generate(&GeneratorParams::new(5, 2, 4, 1)).unwrap();
+        let second = generate(&GeneratorParams::new(5, 2, 4, 2)).unwrap();
+
+        assert_ne!(first.level, second.level);
+    }
+
+    #[test]
+    fn generated_level_has_requested_shape_and_is_solvable() {
+        let generated = generate(&GeneratorParams::new(6, 2, 4, 99)).unwrap();
+        let mut w = generated.level.clone();
+
+        assert_eq!(w.bottles_count(), 8);
+        assert!(w.can_be_sorted());
+        assert!(!w.win());
+        generated.solution.iter().for_each(|p| w.pour(p.from, p.to));
+        assert!(w.win());
+    }
+
+    #[test]
+    fn smaller_capacity_is_supported() {
+        let generated = generate(&GeneratorParams::new(3, 1, 3, 5)).unwrap();
+
+        assert_eq!(generated.level.capacity(), 3);
+        assert!(generated.level.can_be_sorted());
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        assert!(matches!(generate(&GeneratorParams::new(0, 2, 4, 1)), Err(GeneratorError::InvalidParams(_))));
+        assert!(matches!(generate(&GeneratorParams::new(14, 2, 4, 1)), Err(GeneratorError::InvalidParams(_))));
+        assert!(matches!(generate(&GeneratorParams::new(3, 2, 5, 1)), Err(GeneratorError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn level_is_generated_within_requested_difficulty_band() {
+        let params = GeneratorParams::new(4, 2, 4, 3).with_difficulty(40.0, 50.0);
+
+        let generated = generate(&params).unwrap();
+
+        let rating = generated.rating.unwrap();
+        assert!(rating.score >= 40.0 && rating.score <= 50.0);
+        assert!(generated.attempts > 1);
+        assert_eq!(generate(&params).unwrap().level, generated.level);
+    }
+
+    #[test]
+    fn unreachable_difficulty_band_is_reported() {
+        let params = GeneratorParams { max_attempts: 5, ..GeneratorParams::new(2, 1, 4, 3).with_difficulty(99.0, 100.0) };
+
+        assert!(matches!(generate(&params), Err(GeneratorError::OutsideDifficultyBand { attempts: 5, .. })));
+    }
+
+    #[test]
+    fn inverted_difficulty_band_is_rejected() {
+        let params = GeneratorParams::new(4, 2, 4, 1).with_difficulty(60.0, 40.0);
+
+        assert!(matches!(generate(&params), Err(GeneratorError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn generated_level_meets_aesthetic_constraints() {
+        let constraints = GenerationConstraints { no_solved_bottles: true, max_run_length: 1, min_distinct_colors: 3, ..GenerationConstraints::new() };
+        let params = GeneratorParams::new(5, 2, 4, 8).with_constraints(constraints);
+
+        let generated = generate(&params).unwrap();
+
+        let layout = generated.level.bottles.iter().filter(|b| !b.is_empty()).map(|b| b.layers().iter().flatten().copied().collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert!(layout_violations(&constraints, &layout).is_empty());
+        assert!(generated.level.bottles.iter().all(|b| !b.is_solved()));
+    }
+
+    #[test]
+    fn layout_violations_are_detected() {
+        let constraints = GenerationConstraints { no_solved_bottles: true, max_run_length: 2, min_distinct_colors: 2, ..GenerationConstraints::new() };
+        let layout = [
+            [Color::Red, Color::Red, Color::Red, Color::Red].to_vec(),
+            [Color::Blue, Color::Green, Color::Green, Color::Green].to_vec(),
+        ];
+
+        assert_eq!(layout_violations(&constraints, &layout), [
+            ConstraintViolation::SolvedBottle,
+            ConstraintViolation::RunTooLong,
+            ConstraintViolation::TooFewDistinctColors,
+        ].to_vec());
+    }
+
+    #[test]
+    fn required_empty_bottles_are_really_needed() {
+        let constraints = GenerationConstraints { required_empty_bottles: Some(2), ..GenerationConstraints::new() };
+        let params = GeneratorParams::new(3, 2, 4, 4).with_constraints(constraints);
+
+        let generated = generate(&params).unwrap();
+
+        let analysis = minimum_empty_bottles(&generated.level, 2, usize::MAX).unwrap();
+        assert_eq!(analysis.empty_bottles, 2);
+    }
+
+    #[test]
+    fn impossible_constraints_are_reported_up_front() {
+        let constraints = GenerationConstraints { min_distinct_colors: 5, required_empty_bottles: Some(3), ..GenerationConstraints::new() };
+        let params = GeneratorParams::new(4, 2, 4, 1).with_constraints(constraints);
+
+        assert_eq!(generate(&params), Err(GeneratorError::ImpossibleConstraints([
+            ConstraintViolation::TooFewDistinctColors,
+            ConstraintViolation::EmptyBottlesNotRequired,
+        ].to_vec())));
+    }
+
+    #[test]
+    fn constraints_not_met_in_time_are_reported_with_counts() {
+        let constraints = GenerationConstraints { max_run_length: 1, min_distinct_colors: 4, ..GenerationConstraints::new() };
+        let params = GeneratorParams { max_attempts: 4, ..GeneratorParams::new(4, 2, 4, 1).with_constraints(constraints) };
+
+        match generate(&params) {
+            Err(GeneratorError::ConstraintsNotMet { attempts, violations }) => {
+                assert_eq!(attempts, 4);
+                assert!(violations.iter().map(|(_, count)| count).sum::<usize>() >= 4);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_failure_when_no_candidate_is_solvable() {
+        let params = GeneratorParams { max_attempts: 3, ..GeneratorParams::new(4, 0, 4, 1) };
+
+        assert_eq!(generate(&params), Err(GeneratorError::NoSolvableLevel { attempts: 3 }));
+    }
+}
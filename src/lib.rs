@@ -1,9 +1,34 @@
 use core::fmt::Debug;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 use wasm_bindgen::prelude::*;
 use std::ops::Add;
 
+pub mod analysis;
+pub mod campaign;
+pub mod daily;
+pub mod duplicates;
+pub mod evolution;
+pub mod frozen;
+pub mod generator;
+pub mod optimizer;
+pub mod pack;
+pub mod powerup;
+pub mod proof;
+mod rng;
+pub mod rules;
+pub mod scoring;
+pub mod stats;
+pub mod strategy;
+pub mod timer;
+
+use frozen::ThawTrigger;
+use rules::{PourRejection, RuleMode, RuleSet, WinCondition};
+use stats::SolverStats;
+use strategy::{PruningRules, SolverConfig};
+use timer::Timer;
+
 #[wasm_bindgen]
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Color {
@@ -21,6 +46,26 @@ pub enum Color {
     LightBlue,
     Peach,
     Olive,
+    // wildcard liquid: sits on top of any color and takes any color on top of it
+    Rainbow,
+}
+
+pub(crate) const COLORS: [Color; 13] = [
+    Color::Blue, Color::Red, Color::Gray, Color::Orange, Color::Brown, Color::Yellow, Color::Green,
+    Color::Magenta, Color::Teal, Color::Purple, Color::LightBlue, Color::Peach, Color::Olive,
+];
+
+// a bottle holds at most four layers of liquid
+pub const MAX_CAPACITY: usize = 4;
+
+// set on every layer of a capped bottle in the `bottles` output
+pub const CAPPED_MARKER: u8 = 0x80;
+
+impl Color {
+    // whether one of the colors can be poured onto the other
+    pub(crate) fn blends_with(self, other: Color) -> bool {
+        self == other || self == Color::Rainbow || other == Color::Rainbow
+    }
 }
 
 impl Debug for Color {
@@ -39,19 +84,25 @@ impl Debug for Color {
             Self::LightBlue => write!(f, "L"),
             Self::Peach => write!(f, "P"),
             Self::Olive => write!(f, "O"),
+            Self::Rainbow => write!(f, "🌈"),
             Self::Empty => write!(f, ""),
         }
     }
 }
 
 #[wasm_bindgen]
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone)]
 struct Bottle {
     index: Option<usize>,
+    capacity: usize,
     bottom: Option<Color>,
     l1: Option<Color>,
     l2: Option<Color>,
     top: Option<Color>,
+    // the only color the bottle takes, rainbow aside
+    color_lock: Option<Color>,
+    // layers that cannot be poured out until they thaw, bottom first
+    frozen: [bool; MAX_CAPACITY],
 }
 
 #[wasm_bindgen]
@@ -75,14 +126,13 @@ impl PartialEq for Pour {
 
 impl Debug for Bottle {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{:#?}{:#?}{:#?}{:#?}",
-            self.bottom.unwrap_or(Color::Empty),
-            self.l1.unwrap_or(Color::Empty),
-            self.l2.unwrap_or(Color::Empty),
-            self.top.unwrap_or(Color::Empty)
-        )
+        for (layer, frozen) in self.layers().iter().zip(self.frozen) {
+            write!(f, "{:#?}", layer.unwrap_or(Color::Empty))?;
+            if frozen {
+                write!(f, "❄")?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -102,10 +152,13 @@ impl Bottle {
     ) -> Self {
         Bottle {
             index,
+            capacity: MAX_CAPACITY,
             bottom,
             l1,
             l2,
             top,
+            color_lock: None,
+            frozen: [false; MAX_CAPACITY],
         }
     }
 
@@ -129,6 +182,21 @@ impl Bottle {
         Bottle::new(Some(idx), Some(b), Some(l1), Some(l2), Some(t))
     }
 
+    pub fn with_capacity(idx: usize, capacity: usize, colors: &[Color]) -> Self {
+        assert!(capacity <= MAX_CAPACITY && colors.len() <= capacity, "Bottle cannot hold {} of {} layers", colors.len(), capacity);
+        let mut layers = colors.iter().copied().map(Some).chain([None; MAX_CAPACITY]);
+        Bottle {
+            index: Some(idx),
+            capacity,
+            bottom: layers.next().unwrap(),
+            l1: layers.next().unwrap(),
+            l2: layers.next().unwrap(),
+            top: layers.next().unwrap(),
+            color_lock: None,
+            frozen: [false; MAX_CAPACITY],
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         matches!(
             (self.bottom, self.l1, self.l2, self.top),
@@ -137,7 +205,7 @@ impl Bottle {
     }
 
     pub fn is_full(&self) -> bool {
-        matches!((self.bottom, self.l1, self.l2, self.top), (Some(_), Some(_), Some(_), Some(_)))
+        self.available_empty_space() == 0
     }
 
     pub fn top_color(&self) -> Option<Color> {
@@ -150,6 +218,18 @@ impl Bottle {
         }
     }
 
+    fn layers(&self) -> [Option<Color>; 4] {
+        [self.bottom, self.l1, self.l2, self.top]
+    }
+
+    fn frozen_mask(&self) -> u8 {
+        self.frozen.iter().enumerate().filter(|(_, &frozen)| frozen).map(|(layer, _)| 1 << layer).sum()
+    }
+
+    fn top_is_frozen(&self) -> bool {
+        self.layers().iter().rposition(Option::is_some).is_some_and(|layer| self.frozen[layer])
+    }
+
     fn pop(&mut self) {
         match (self.top, self.l2, self.l1, self.bottom) {
             (None, None, None, None) => panic!("Should not happen"),
@@ -158,23 +238,29 @@ impl Bottle {
             (None, _, _, _) => self.l2 = None,
             (_, _, _, _) => self.top = None,
         };
+        if let Some(slot) = self.layers().iter().position(Option::is_none) {
+            self.frozen[slot] = false;
+        }
     }
 
     pub fn pour(&mut self, c: Color) -> bool {
+        if self.is_full() || !self.accepts(c) {
+            return false;
+        }
         match (self.top, self.l2, self.l1, self.bottom) {
             (None, None, None, None) => {
                 self.bottom = Some(c);
                 true
             }
-            (None, None, None, Some(s)) if s == c => {
+            (None, None, None, Some(s)) if s.blends_with(c) => {
                 self.l1 = Some(c);
                 true
             }
-            (None, None, Some(s), Some(_)) if s == c => {
+            (None, None, Some(s), Some(_)) if s.blends_with(c) => {
                 self.l2 = Some(c);
                 true
             }
-            (None, Some(s), Some(_), Some(_)) if s == c => {
+            (None, Some(s), Some(_), Some(_)) if s.blends_with(c) => {
                 self.top = Some(c);
                 true
             }
@@ -182,50 +268,57 @@ impl Bottle {
         }
     }
 
+    fn accepts(&self, c: Color) -> bool {
+        self.color_lock.map_or(true, |lock| lock.blends_with(c))
+    }
+
+    // whether the top `amount` layers of `source` may go into this bottle
+    fn accepts_top_of(&self, source: &Bottle, amount: usize) -> bool {
+        source.layers().iter().rev().flatten().take(amount).all(|&c| self.accepts(c))
+    }
+
+    // rainbow layers count as whatever color the rest of the bottle is
     pub fn is_empty_or_one_color(&self) -> bool {
-        match (self.bottom, self.l1, self.l2, self.top) {
-            (None, None, None, None) => true,
-            (Some(b), Some(l1), Some(l2), Some(t)) if t == l2 && l2 == l1 && l1 == b => true,
-            (Some(b), Some(l1), Some(l2), None) if b == l2 && l2 == l1 => true,
-            (Some(b), Some(l1), None, None) if b == l1 => true,
-            (Some(_), None, None, None) => true,
-            _ => false,
+        let mut colors = self.layers().into_iter().flatten().filter(|&c| c != Color::Rainbow);
+        match colors.next() {
+            None => true,
+            Some(first) => colors.all(|c| c == first),
         }
     }
 
     pub fn is_solved(&self) -> bool {
-        match (self.bottom, self.l1, self.l2, self.top) {
-            (Some(b), Some(l1), Some(l2), Some(t)) if t == l2 && l2 == l1 && l1 == b => true,
-            _ => false,
-        }
+        !self.is_empty() && self.is_full() && self.is_empty_or_one_color()
     }
 
     pub fn available_empty_space(&self) -> usize {
-        match (self.bottom, self.l1, self.l2, self.top) {
-            (None, None, None, None) => 4,
-            (Some(_), None, None, None) => 3,
+        let filled = match (self.bottom, self.l1, self.l2, self.top) {
+            (None, None, None, None) => 0,
+            (Some(_), None, None, None) => 1,
             (Some(_), Some(_), None, None) => 2,
-            (Some(_), Some(_), Some(_), None) => 1,
-            (Some(_), Some(_), Some(_), Some(_)) => 0,
+            (Some(_), Some(_), Some(_), None) => 3,
+            (Some(_), Some(_), Some(_), Some(_)) => 4,
             _ => panic!("Should not happen"),
-        }
+        };
+        self.capacity - filled
     }
 
+    // the top run, rainbow layers join the run of the color next to them and frozen layers end it
     pub fn amount_to_pour(&self) -> usize {
-        match (self.bottom, self.l1, self.l2, self.top) {
-            (None, None, None, None) => 0,
-            (Some(_), None, None, None) => 1,
-            (Some(b), Some(l1), None, None) if b == l1 => 2,
-            (Some(b), Some(l1), None, None) if b != l1 => 1,
-            (Some(b), Some(l1), Some(l2), None) if b == l1 && l1 == l2 => 3,
-            (Some(b), Some(l1), Some(l2), None) if l2 == l1 && l1 != b => 2,
-            (Some(_), Some(l1), Some(l2), None) if l2 != l1 => 1,
-            (Some(b), Some(l1), Some(l2), Some(t)) if t == l2 && l2 == l1 && l1 == b => 4,
-            (Some(_), Some(l1), Some(l2), Some(t)) if t == l2 && l2 == l1 => 3,
-            (Some(_), Some(_), Some(l2), Some(t)) if t == l2 => 2,
-            (Some(_), Some(_), Some(l2), Some(t)) if t != l2 => 1,
-            _ => panic!("Should not happen"),
+        let mut run_color = None;
+        let mut amount = 0;
+        for (&layer, frozen) in self.layers().iter().zip(self.frozen).rev().filter_map(|(l, f)| Some((l.as_ref()?, f))) {
+            if frozen {
+                break;
+            }
+            if layer != Color::Rainbow {
+                match run_color {
+                    Some(color) if color != layer => break,
+                    _ => run_color = Some(layer),
+                }
+            }
+            amount += 1;
         }
+        amount
     }
 }
 
@@ -235,6 +328,15 @@ pub struct WaterSorting {
     bottles: Vec<Bottle>,
     old_state: Option<Vec<Bottle>>,
     bottles_serialized: Vec<u8>,
+    locks_serialized: Vec<u8>,
+    frozen_serialized: Vec<u8>,
+    // pours that changed the level, minus the ones taken back
+    moves: usize,
+    undos: usize,
+    extra_bottles_used: usize,
+    rules: RuleSet,
+    thaw_trigger: ThawTrigger,
+    timer: Option<Timer>,
 }
 
 impl Debug for WaterSorting {
@@ -248,7 +350,8 @@ impl Debug for WaterSorting {
 impl Display for WaterSorting {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         self.bottles.iter().enumerate().for_each(|(i, x)|{
-            f.write_fmt(format_args!("{:?}: {:?}\n", i+1, x)).expect("");
+            let cap = if self.is_capped(i) { " (capped)" } else { "" };
+            f.write_fmt(format_args!("{:?}: {:?}{}\n", i+1, x, cap)).expect("");
             ()
         });
         Ok(())
@@ -257,7 +360,7 @@ impl Display for WaterSorting {
 
 impl PartialEq<Self> for WaterSorting {
     fn eq(&self, other: &Self) -> bool {
-        if self.bottles.len() != other.bottles.len() { false }
+        if self.bottles.len() != other.bottles.len() || self.thaw_countdown() != other.thaw_countdown() { false }
         else {
             for i in 0..self.bottles.len() {
                 if !self.bottles[i].eq(&other.bottles[i]) { return false; }
@@ -267,49 +370,134 @@ impl PartialEq<Self> for WaterSorting {
     }
 }
 
+impl Eq for WaterSorting {}
+
+impl Hash for WaterSorting {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bottles.hash(state);
+        self.thaw_countdown().hash(state)
+    }
+}
+
 #[wasm_bindgen]
 impl WaterSorting {
     pub fn new() -> Self {
         WaterSorting {
             bottles: Vec::with_capacity(4),
             old_state: None,
-            bottles_serialized: Vec::with_capacity(16)
+            bottles_serialized: Vec::with_capacity(16),
+            locks_serialized: Vec::with_capacity(4),
+            frozen_serialized: Vec::with_capacity(4),
+            moves: 0,
+            undos: 0,
+            extra_bottles_used: 0,
+            rules: RuleSet::water(),
+            thaw_trigger: ThawTrigger::default(),
+            timer: None,
         }
     }
 
+    pub fn with_rules(rules: RuleSet) -> Self {
+        WaterSorting { rules, ..Self::new() }
+    }
+
+    pub fn with_rule_mode(rule_mode: RuleMode) -> Self {
+        Self::with_rules(RuleSet::for_mode(rule_mode))
+    }
+
+    pub fn rules(&self) -> RuleSet {
+        self.rules
+    }
+
     pub fn pour(&mut self, from_index: usize, to_index: usize) {
-        self.old_state = Some(self.bottles.to_vec());
-        loop {
-            match self.bottles[from_index].top_color() {
-                None => break,
-                Some(b) => {
-                    let to_b = &mut self.bottles[to_index];
-                    let r = to_b.pour(b);
-                    if !r {
-                        break;
-                    }
-                    self.bottles[from_index].pop();
-                }
-            }
+        let _ = self.try_pour(from_index, to_index);
+    }
+
+    /// Why the rules refuse pouring from `from_index` into `to_index`, `None` when the pour is legal.
+    pub fn check_pour(&self, from_index: usize, to_index: usize) -> Option<PourRejection> {
+        if from_index >= self.bottles.len() || to_index >= self.bottles.len() {
+            return Some(PourRejection::NoSuchBottle);
+        }
+        if self.moves_left() == Some(0) {
+            return Some(PourRejection::OutOfMoves);
+        }
+        if self.time_up() {
+            return Some(PourRejection::TimeUp);
+        }
+        let (src, dst) = (&self.bottles[from_index], &self.bottles[to_index]);
+        if from_index == to_index {
+            Some(PourRejection::SameBottle)
+        } else if src.is_empty() {
+            Some(PourRejection::EmptySource)
+        } else if src.top_is_frozen() {
+            Some(PourRejection::Frozen)
+        } else if self.is_capped(from_index) {
+            Some(PourRejection::BottleLocked)
+        } else if dst.is_full() {
+            Some(PourRejection::DestinationFull)
+        } else if !dst.is_empty() && !dst.top_color().unwrap().blends_with(src.top_color().unwrap()) {
+            Some(PourRejection::ColorMismatch)
+        } else if !self.lock_accepts_pour(from_index, to_index) {
+            Some(PourRejection::ColorLocked)
+        } else if !self.rules.split_runs && self.pour_amount(from_index) > dst.available_empty_space() {
+            Some(PourRejection::RunDoesNotFit)
+        } else {
+            None
         }
     }
 
     pub fn undo(&mut self) {
+        if !self.undo_available() {
+            return;
+        }
         match &self.old_state {
             None => {}
             Some(old) => {
+                // taking back an extra bottle gives it back to the budget, it was no pour
+                if old.len() < self.bottles.len() {
+                    self.extra_bottles_used -= 1;
+                } else if *old != self.bottles {
+                    self.moves -= 1;
+                }
+                self.undos += 1;
                 self.bottles = old.to_vec();
-                self.old_state = None
+                self.old_state = None;
+                self.time_undo();
             }
         }
     }
 
+    pub fn moves_made(&self) -> usize {
+        self.moves
+    }
+
+    pub fn undos_made(&self) -> usize {
+        self.undos
+    }
+
     pub fn undo_available(&self) -> bool {
-        self.old_state.is_some()
+        self.old_state.is_some() && !self.lost() && self.undos_left() != Some(0)
+    }
+
+    /// Pours left under the move limit, `None` without a limit.
+    pub fn moves_left(&self) -> Option<usize> {
+        self.rules.max_moves.map(|max| max.saturating_sub(self.moves))
+    }
+
+    pub fn undos_left(&self) -> Option<usize> {
+        self.rules.max_undos.map(|max| max.saturating_sub(self.undos))
+    }
+
+    /// The move limit was reached or the time ran out without a win; no more pours or undos are accepted.
+    pub fn lost(&self) -> bool {
+        (self.moves_left() == Some(0) || self.time_up()) && !self.win()
     }
 
     pub fn win(&self) -> bool {
-        self.bottles.iter().all(|b| b.is_empty_or_one_color() && (b.is_empty() || b.is_full()))
+        match self.rules.win_condition {
+            WinCondition::FullBottles => self.bottles.iter().all(|b| b.is_empty_or_one_color() && (b.is_empty() || b.is_full())),
+            WinCondition::SingleColor => self.bottles.iter().all(|b| b.is_empty_or_one_color()),
+        }
     }
 
     pub fn init_empty_bottle(&mut self) {
@@ -332,8 +520,23 @@ impl WaterSorting {
         self.bottles.push(Bottle::with_four_colors(self.bottles.iter().count(), b, l1, l2, t))
     }
 
+    pub fn init_empty_bottle_with_capacity(&mut self, capacity: usize) {
+        self.bottles.push(Bottle::with_capacity(self.bottles.len(), capacity, &[]))
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bottles.iter().map(|b| b.capacity).max().unwrap_or(MAX_CAPACITY)
+    }
+
     pub fn move_available(&self) -> bool {
-        if self.bottles.iter().any(|b| b.is_empty()) {
+        // an empty bottle helps unless everything there is to pour into it is capped, frozen or,
+        // for a locked bottle, of another color
+        let stuck = |to: usize| {
+            let mut sources = self.bottles.iter().enumerate().filter(|(_, src)| !src.is_empty()).peekable();
+            sources.peek().is_some() &&
+                sources.all(|(from, src)| self.is_capped(from) || src.top_is_frozen() || !self.lock_accepts_pour(from, to))
+        };
+        if self.bottles.iter().enumerate().any(|(to, b)| b.is_empty() && !stuck(to)) {
             return true;
         }
 
@@ -342,7 +545,9 @@ impl WaterSorting {
             let (src_color, src_is_full) = top_src;
             for (dst,top_dst) in top_colors.clone() {
                 let (dst_color, dst_is_full) = top_dst;
-                if src != dst && src_color == dst_color {
+                // a capped bottle takes part in no pour, either way
+                if src != dst && src_color.blends_with(dst_color) && !self.bottles[src].top_is_frozen() &&
+                    !self.is_capped(src) && !self.is_capped(dst) && self.lock_accepts_pour(src, dst) {
                     return !src_is_full || !dst_is_full
                 }
             }
@@ -350,59 +555,70 @@ impl WaterSorting {
         false
     }
 
-    fn next_available_move(&self) -> Option<Pour> {
-        self.next_available_move_except(&Vec::new())
+    fn top_colors(&self) -> Vec<(usize, (Color, bool))> {
+        self.bottles
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| !b.is_empty())
+            .map(|(i, b)| (i, (b.top_color().unwrap(), b.is_full())))
+            .collect::<Vec<(usize, (Color, bool))>>()
     }
 
-    fn next_available_moves(&self) -> Vec<Pour> {
-        let mut moves = Vec::new();
-        while let Some(pour) = self.next_available_move_except(&moves) {
-            moves.push(pour);
-        }
+    // units the rules let a pour from `from` move, if the destination has room for them
+    pub(crate) fn pour_amount(&self, from: usize) -> usize {
+        let run = self.bottles[from].amount_to_pour();
+        self.rules.pour_limit.map_or(run, |limit| run.min(limit))
+    }
 
-        moves
+    // whether the rules allow pouring from `from` into `to` at all, ignoring colors
+    pub(crate) fn pour_allowed(&self, from: usize, to: usize) -> bool {
+        !self.is_capped(from) && !self.is_capped(to) &&
+            (self.rules.split_runs || self.pour_amount(from) <= self.bottles[to].available_empty_space())
     }
 
-    fn next_available_move_except(&self, moves: &Vec<Pour>) -> Option<Pour> {
-        let empty_bottles = self.bottles.iter().filter(|b| b.is_empty());
+    // whether the lock of `to` takes every layer a pour from `from` would move
+    pub(crate) fn lock_accepts_pour(&self, from: usize, to: usize) -> bool {
+        let moved = self.pour_amount(from).min(self.bottles[to].available_empty_space());
+        self.bottles[to].accepts_top_of(&self.bottles[from], moved)
+    }
 
-        for empty in empty_bottles {
-            let non_empty_bottles = self.bottles.iter().filter(|b| !b.is_empty());
-            for non_empty in non_empty_bottles {
-                // do not list as available moves pouring from solved one to an empty one
-                if non_empty.is_solved() {
+    pub(crate) fn possible_moves(&self, pruning: &PruningRules) -> Vec<Pour> {
+        // while layers wait for enough moves to thaw, moves that only pass time are useful
+        let skip_single_color_to_empty = pruning.skip_single_color_to_empty && self.thaw_countdown().is_none();
+        let mut moves = Vec::new();
+        if self.moves_left() == Some(0) {
+            return moves;
+        }
+        // empty bottles are interchangeable only when they have the same size and lock
+        let mut empty_destinations_seen = Vec::new();
+        // capped bottles are left out up front, they can neither give nor take
+        let capped = self.bottles.iter().enumerate().map(|(i, _)| self.is_capped(i)).collect::<Vec<_>>();
+        for (to, dst) in self.bottles.iter().enumerate() {
+            if dst.is_full() {
+                continue
+            }
+            if dst.is_empty() {
+                if pruning.skip_symmetric_empty_bottles && empty_destinations_seen.contains(&(dst.capacity, dst.color_lock)) {
                     continue
                 }
-                let pour = Pour::new(non_empty.index.unwrap(), empty.index.unwrap());
-                if !moves.contains(&pour) {
-                    return Some(pour);
-                }
+                empty_destinations_seen.push((dst.capacity, dst.color_lock));
             }
-        }
-
-        let top_colors = self.top_colors();
-        for (src_index, t1) in top_colors.clone() {
-            let (src_color, _) = t1;
-            for (dst_index,t2) in top_colors.clone() {
-                let (dst_color, dst_is_full) = t2;
-                let pour = Pour::new(src_index, dst_index);
-                // do not list as available moves pouring only part of color to different bottle
-                if src_index != dst_index && src_color == dst_color && !dst_is_full && !moves.contains(&pour) &&
-                    self.bottles[src_index].amount_to_pour() <= self.bottles[dst_index].available_empty_space() {
-                    return Some(pour)
+            for (from, src) in self.bottles.iter().enumerate() {
+                if from == to || capped[from] || src.is_empty() || src.top_is_frozen() || (!dst.is_empty() && !dst.top_color().unwrap().blends_with(src.top_color().unwrap())) ||
+                    !self.lock_accepts_pour(from, to) || !self.pour_allowed(from, to) {
+                    continue
+                }
+                if (pruning.skip_solved_bottles && src.is_solved()) ||
+                    (pruning.skip_partial_pours && self.pour_amount(from) > dst.available_empty_space()) ||
+                    (skip_single_color_to_empty && dst.is_empty() && src.is_empty_or_one_color()) {
+                    continue
                 }
+                moves.push(Pour::new(from, to));
             }
         }
-        None
-    }
-
-    fn top_colors(&self) -> Vec<(usize, (Color, bool))> {
-        self.bottles
-            .iter()
-            .filter(|b| !b.is_empty())
-            .map(|b| (b.top_color().unwrap(), b.is_full()))
-            .enumerate()
-            .collect::<Vec<(usize, (Color, bool))>>()
+        // pours into empty bottles first, then the others by source, the order moves were always tried in
+        moves.sort_by_key(|p| if self.bottles[p.to].is_empty() { (0, p.to, p.from) } else { (1, p.from, p.to) });
+        moves
     }
 
     fn map_color_to_u8(c: Option<Color>) -> u8 {
@@ -424,6 +640,7 @@ impl WaterSorting {
                     Color::LightBlue => {11},
                     Color::Peach => {12},
                     Color::Olive => {13},
+                    Color::Rainbow => {14},
                 }
             }
         }
@@ -433,19 +650,56 @@ impl WaterSorting {
 
         self.bottles_serialized = self.bottles
             .iter()
-            .flat_map(|b|  [Self::map_color_to_u8(b.bottom),
-                            Self::map_color_to_u8(b.l1),
-                            Self::map_color_to_u8(b.l2),
-                            Self::map_color_to_u8(b.top)]).collect::<Vec<_>>();
+            .enumerate()
+            .flat_map(|(i, b)|  {
+                let marker = if self.is_capped(i) { CAPPED_MARKER } else { 0 };
+                [Self::map_color_to_u8(b.bottom),
+                 Self::map_color_to_u8(b.l1),
+                 Self::map_color_to_u8(b.l2),
+                 Self::map_color_to_u8(b.top)].map(|c| c | marker)
+            }).collect::<Vec<_>>();
         self.bottles_serialized.as_ptr()
     }
 
+    /// One byte per bottle: the color the bottle is locked to, encoded like `bottles`, or 0.
+    pub fn bottle_color_locks(&mut self) -> *const u8 {
+        self.locks_serialized = self.bottles.iter().map(|b| Self::map_color_to_u8(b.color_lock)).collect();
+        self.locks_serialized.as_ptr()
+    }
+
+    /// Lets bottle `index` only take `color` (and rainbow) from now on.
+    pub fn lock_bottle_to_color(&mut self, index: usize, color: Color) {
+        self.bottles[index].color_lock = Some(color);
+    }
+
+    pub fn bottle_color_lock(&self, index: usize) -> Option<Color> {
+        self.bottles[index].color_lock
+    }
+
+    /// Completed bottle closed by the capping rule (`lock_completed`), it takes no part in any pour.
+    pub fn is_capped(&self, index: usize) -> bool {
+        self.rules.lock_completed && self.bottles[index].is_solved()
+    }
+
     pub fn bottles_count(&self) -> usize {
         self.bottles.iter().count()
     }
 
     pub fn reset(&mut self) {
         self.bottles.clear();
+        self.old_state = None;
+        self.moves = 0;
+        self.undos = 0;
+        self.extra_bottles_used = 0;
+    }
+
+    // locked empty bottles stay, they only take their color and are no plain empty bottles
+    pub(crate) fn without_empty_bottles(&self) -> WaterSorting {
+        let mut level = WaterSorting { thaw_trigger: self.thaw_trigger, ..WaterSorting::with_rules(self.rules) };
+        for bottle in self.bottles.iter().filter(|b| !b.is_empty() || b.color_lock.is_some()) {
+            level.bottles.push(Bottle { index: Some(level.bottles.len()), ..*bottle });
+        }
+        level
     }
 
     pub fn render(&self) -> String {
@@ -465,7 +719,48 @@ impl WaterSorting {
                 }
             }
         }
-        hash_map.iter().filter(|e| *e.1 != 4).count() == 0
+        // rainbow layers make up for missing layers, any left over have to fill whole bottles
+        let capacity = self.capacity();
+        let rainbows = hash_map.remove(&Color::Rainbow).unwrap_or(0) as usize;
+        if hash_map.values().any(|&count| count as usize > capacity) {
+            return false;
+        }
+        let missing = hash_map.values().map(|&count| capacity - count as usize).sum::<usize>();
+        missing <= rainbows && (rainbows - missing) % capacity.max(1) == 0
+    }
+}
+
+impl WaterSorting {
+    /// Pours like `pour`, returning the number of units moved or why the rules refused the pour.
+    /// A refused pour leaves the bottles and the pour to undo unchanged.
+    pub fn try_pour(&mut self, from_index: usize, to_index: usize) -> Result<usize, PourRejection> {
+        if let Some(rejection) = self.check_pour(from_index, to_index) {
+            return Err(rejection);
+        }
+        self.old_state = Some(self.bottles.to_vec());
+        let mut moved = 0;
+        for _ in 0..self.pour_amount(from_index) {
+            let color = self.bottles[from_index].top_color().unwrap();
+            if !self.bottles[to_index].pour(color) {
+                break;
+            }
+            self.bottles[from_index].pop();
+            moved += 1;
+        }
+        self.moves += 1;
+        self.thaw_if_triggered(to_index);
+        self.time_pour();
+        Ok(moved)
+    }
+
+    /// Same level with other rules, without any moves made.
+    pub fn under_rules(&self, rules: RuleSet) -> WaterSorting {
+        WaterSorting { bottles: self.bottles.clone(), thaw_trigger: self.thaw_trigger, ..WaterSorting::with_rules(rules) }
+    }
+
+    /// Adds a bottle holding `colors` (bottom first) that has room for `capacity` layers.
+    pub fn init_bottle_with_colors(&mut self, capacity: usize, colors: &[Color]) {
+        self.bottles.push(Bottle::with_capacity(self.bottles.len(), capacity, colors))
     }
 }
 
@@ -473,57 +768,73 @@ impl WaterSorting {
 pub struct WaterSolver {
     level: WaterSorting,
     solution: Vec<usize>,
+    stats: SolverStats,
+    config: SolverConfig,
 }
 
 #[wasm_bindgen]
 impl WaterSolver {
     pub fn new(w: &WaterSorting) -> WaterSolver {
-        WaterSolver{ level: w.clone(), solution: Vec::new() }
+        Self::with_config(w, SolverConfig::new())
+    }
+
+    pub fn with_config(w: &WaterSorting, config: SolverConfig) -> WaterSolver {
+        // the solver plays against the puzzle, not the clock
+        let level = WaterSorting { timer: None, ..w.clone() };
+        WaterSolver{ level, solution: Vec::new(), stats: SolverStats::default(), config }
+    }
+
+    pub fn config(&self) -> SolverConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: SolverConfig) {
+        self.config = config;
     }
 
     pub fn solution(&mut self, n: usize) -> *const usize {
-        let solution = self.solve(n);
-        self.solution = solution
-            .iter()
-            .flat_map(|p| [p.from, p.to]).collect::<Vec<_>>();
-        self.solution.insert(0, solution.len());
-        self.solution.as_ptr()
+        let (solution, stats) = self.solve_with_stats(n);
+        self.store_solution(&solution, stats)
+    }
+
+    pub fn configured_solution(&mut self) -> *const usize {
+        let (solution, stats) = self.solve_configured();
+        self.store_solution(&solution, stats)
+    }
+
+    pub fn stats(&self) -> SolverStats {
+        self.stats.clone()
     }
 
     pub fn solve(&self, n: usize) -> Vec<Pour> {
-        let moves = Vec::new();
-        let new_w = self.level.clone();
-        let mut old_states = Vec::new();
-        old_states.push(new_w.clone());
-        self.solve_internal(new_w, moves, old_states, n).unwrap_or_else(|| Vec::new())
+        self.solve_with_stats(n).0
     }
+}
 
-    fn solve_internal(&self, existing_state: WaterSorting, moves: Vec<Pour>, old_states: Vec<WaterSorting>, level: usize) -> Option<Vec<Pour>> {
-        if existing_state.win() {
-            return Some(moves)
-        }
-        if level == 0 {
-            return None
-        }
-        let next_available_moves = existing_state.next_available_moves();
-        for next_move in next_available_moves {
-            let mut possible_solution = moves.clone();
-            let mut new_state = existing_state.clone();
-            possible_solution.push(Pour::new(next_move.from, next_move.to));
-            new_state.pour(next_move.from, next_move.to);
-            let mut existing_states = old_states.clone();
-            if existing_states.iter().any(|f| f.eq(&new_state.clone()))
-            {
-                return None;
-            }
-            existing_states.push(new_state.clone());
-            let result = self.solve_internal(new_state, possible_solution, existing_states, level-1);
-            match result {
-                None => {}
-                r => return r
-            }
-        }
-        None
+impl WaterSolver {
+    pub fn solve_with_stats(&self, n: usize) -> (Vec<Pour>, SolverStats) {
+        self.solve_using(&SolverConfig { max_depth: n, ..self.config })
+    }
+
+    pub fn solve_configured(&self) -> (Vec<Pour>, SolverStats) {
+        self.solve_using(&self.config)
+    }
+
+    fn solve_using(&self, config: &SolverConfig) -> (Vec<Pour>, SolverStats) {
+        let mut stats = SolverStats::default();
+        let started = stats::now_ms();
+        let solution = config.strategy().solve(&self.level, config, &mut stats).unwrap_or_default();
+        stats.time_ms = stats::now_ms() - started;
+        (solution, stats)
+    }
+
+    fn store_solution(&mut self, solution: &[Pour], stats: SolverStats) -> *const usize {
+        self.stats = stats;
+        self.solution = solution
+            .iter()
+            .flat_map(|p| [p.from, p.to]).collect::<Vec<_>>();
+        self.solution.insert(0, solution.len());
+        self.solution.as_ptr()
     }
 }
 
@@ -747,6 +1058,7 @@ mod equals {
 #[cfg(test)]
 mod next_available_moves_tests {
     use crate::{Color, Pour, WaterSorting};
+    use crate::strategy::PruningRules;
 
     #[test]
     fn next_available_moves_returns_all_the_moves() {
@@ -755,7 +1067,7 @@ mod next_available_moves_tests {
         w.init_bottle_with_one_color(Color::Green);
         w.init_bottle_with_one_color(Color::Green);
 
-        let moves = w.next_available_moves();
+        let moves = w.possible_moves(&PruningRules::none());
 
         let available_moves = [Pour::new(0,1), Pour::new(1,0)].to_vec();
         assert_eq!(moves, available_moves);
@@ -769,7 +1081,7 @@ mod next_available_moves_tests {
         w.init_bottle_with_one_color(Color::Green);
         w.init_empty_bottle();
 
-        let moves = w.next_available_moves();
+        let moves = w.possible_moves(&PruningRules::none());
 
         let available_moves = [Pour::new(0,2), Pour::new(1,2), Pour::new(0,1), Pour::new(1,0)].to_vec();
         assert_eq!(moves, available_moves);
@@ -780,6 +1092,7 @@ mod next_available_moves_tests {
 mod auto_solve_tests {
     use crate::{Color, WaterSolver, WaterSorting};
     use crate::Pour;
+    use crate::strategy::PruningRules;
 
     #[test]
     fn if_no_move_available_next_move_returns_none() {
@@ -787,7 +1100,7 @@ mod auto_solve_tests {
         w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Blue, Color::Blue);
         w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Blue, Color::Red);
 
-        let next_move = w.next_available_move();
+        let next_move = w.possible_moves(&PruningRules::new()).first().copied();
         assert!(next_move.is_none())
     }
 
@@ -797,7 +1110,7 @@ mod auto_solve_tests {
         w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Orange, Color::Orange);
         w.init_empty_bottle();
 
-        let next_move = w.next_available_move();
+        let next_move = w.possible_moves(&PruningRules::new()).first().copied();
         assert!(next_move.is_some());
         assert_eq!(next_move.unwrap(), Pour::new(0usize, 1usize))
     }
@@ -808,7 +1121,7 @@ mod auto_solve_tests {
         w.init_empty_bottle();
         w.init_bottle_with_four_colors(Color::Orange, Color::Orange, Color::Blue, Color::Blue);
 
-        let next_move = w.next_available_move();
+        let next_move = w.possible_moves(&PruningRules::new()).first().copied();
         assert!(next_move.is_some());
         assert_eq!(next_move.unwrap(), Pour::new(1usize, 0usize))
     }
@@ -820,7 +1133,7 @@ mod auto_solve_tests {
         w.init_bottle_with_one_color(Color::Red);
         w.init_bottle_with_two_colors(Color::Red, Color::Blue);
 
-        let next_move = w.next_available_move();
+        let next_move = w.possible_moves(&PruningRules::new()).first().copied();
         assert_eq!(next_move.unwrap(), Pour::new(0usize, 2usize))
     }
 
@@ -831,7 +1144,7 @@ mod auto_solve_tests {
         w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Green);
         w.init_bottle_with_one_color(Color::Red);
 
-        let next_move = w.next_available_move();
+        let next_move = w.possible_moves(&PruningRules::new()).first().copied();
         assert_eq!(next_move.unwrap(), Pour::new(1usize, 0usize))
     }
 
@@ -1131,6 +1444,25 @@ mod water_sorting_tests {
         assert!(w.move_available())
     }
 
+    #[test]
+    fn empty_bottle_does_not_make_a_frozen_run_movable() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_two_colors(Color::Blue, Color::Magenta);
+        w.init_empty_bottle();
+        w.freeze_layer(0, 1);
+
+        assert!(!w.move_available())
+    }
+
+    #[test]
+    fn empty_bottle_does_not_make_a_capped_bottle_movable() {
+        let mut w = WaterSorting::with_rules("cap=yes".parse().unwrap());
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Blue, Color::Blue);
+        w.init_empty_bottle();
+
+        assert!(!w.move_available())
+    }
+
     #[test]
     fn if_there_are_all_full_bottles_move_is_not_available() {
         let mut w = WaterSorting::new();
@@ -1223,4 +1555,172 @@ mod water_sorting_tests {
         }
         assert!(w.win());
     }
+
+    #[test]
+    fn solve_collects_statistics() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Green,Color::Green, Color::Green, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red,Color::Red, Color::Red,Color::Green);
+        w.init_empty_bottle();
+
+        let solver = WaterSolver::new(&w);
+
+        let (moves, stats) = solver.solve_with_stats(10);
+
+        assert_eq!(moves, solver.solve(10));
+        assert_eq!(stats.nodes_expanded, stats.branching_histogram().iter().sum::<usize>());
+        assert!(stats.nodes_expanded >= moves.len());
+        assert_eq!(stats.max_depth, moves.len() - 1);
+        assert!(stats.peak_memory_bytes > 0);
+        assert!(stats.average_branching_factor() > 0.0);
+    }
+
+    #[test]
+    fn solution_keeps_statistics_of_the_last_run() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Green,Color::Green, Color::Green, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red,Color::Red, Color::Red,Color::Green);
+        w.init_empty_bottle();
+
+        let mut solver = WaterSolver::new(&w);
+        assert_eq!(solver.stats().nodes_expanded, 0);
+
+        solver.solution(10);
+
+        let (_, stats) = solver.solve_with_stats(10);
+        assert_eq!(solver.stats().nodes_expanded, stats.nodes_expanded);
+        assert_eq!(solver.stats().branching_histogram(), stats.branching_histogram());
+    }
+}
+
+#[cfg(test)]
+mod rainbow_tests {
+    use crate::{Bottle, Color, WaterSolver, WaterSorting};
+
+    #[test]
+    fn rainbow_can_be_poured_onto_any_color_and_takes_any_color() {
+        let mut b = Bottle::with_one_color(0, Color::Red);
+
+        assert!(b.pour(Color::Rainbow));
+        assert!(b.pour(Color::Blue));
+        assert!(!b.pour(Color::Red));
+    }
+
+    #[test]
+    fn rainbow_joins_the_run_next_to_it() {
+        assert_eq!(Bottle::with_four_colors(0, Color::Blue, Color::Red, Color::Rainbow, Color::Red).amount_to_pour(), 3);
+        assert_eq!(Bottle::with_three_colors(0, Color::Blue, Color::Blue, Color::Rainbow).amount_to_pour(), 3);
+        assert_eq!(Bottle::with_three_colors(0, Color::Red, Color::Blue, Color::Rainbow).amount_to_pour(), 2);
+        assert_eq!(Bottle::with_two_colors(0, Color::Rainbow, Color::Rainbow).amount_to_pour(), 2);
+    }
+
+    #[test]
+    fn rainbow_counts_toward_the_color_of_its_bottle() {
+        assert!(Bottle::with_four_colors(0, Color::Red, Color::Rainbow, Color::Red, Color::Red).is_solved());
+        assert!(Bottle::with_four_colors(0, Color::Rainbow, Color::Rainbow, Color::Rainbow, Color::Rainbow).is_solved());
+        assert!(!Bottle::with_four_colors(0, Color::Red, Color::Rainbow, Color::Blue, Color::Blue).is_solved());
+    }
+
+    #[test]
+    fn rainbow_makes_up_for_missing_layers() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Rainbow, Color::Blue, Color::Red);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Red, Color::Rainbow);
+        assert!(w.can_be_sorted());
+
+        w.init_bottle_with_one_color(Color::Rainbow);
+        assert!(!w.can_be_sorted());
+    }
+
+    #[test]
+    fn solver_uses_rainbow_to_complete_bottles() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Rainbow, Color::Blue, Color::Red);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Red, Color::Rainbow);
+        w.init_empty_bottle();
+
+        let solution = WaterSolver::new(&w).solve_configured().0;
+
+        assert!(!solution.is_empty());
+        solution.iter().for_each(|p| w.pour(p.from, p.to));
+        assert!(w.win());
+    }
+}
+
+#[cfg(test)]
+mod color_lock_tests {
+    use crate::{Color, Pour, WaterSolver, WaterSorting};
+    use crate::rules::PourRejection;
+    use crate::strategy::PruningRules;
+
+    fn level() -> WaterSorting {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Red, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Blue, Color::Blue);
+        w.init_empty_bottle();
+        w.lock_bottle_to_color(2, Color::Blue);
+        w
+    }
+
+    #[test]
+    fn locked_bottle_rejects_other_colors() {
+        let mut w = level();
+
+        assert_eq!(w.try_pour(0, 2), Err(PourRejection::ColorLocked));
+        assert!(w.bottles[2].is_empty());
+        assert_eq!(w.try_pour(1, 2), Ok(2));
+    }
+
+    #[test]
+    fn locked_bottle_takes_rainbow() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_two_colors(Color::Red, Color::Rainbow);
+        w.init_empty_bottle();
+        w.lock_bottle_to_color(1, Color::Blue);
+
+        assert_eq!(w.check_pour(0, 1), Some(PourRejection::ColorLocked));
+        w.init_bottle_with_two_colors(Color::Blue, Color::Rainbow);
+        assert_eq!(w.try_pour(2, 1), Ok(2));
+    }
+
+    #[test]
+    fn only_the_layers_that_move_have_to_match_the_lock() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_two_colors(Color::Red, Color::Rainbow);
+        w.init_bottle_with_three_colors(Color::Blue, Color::Blue, Color::Blue);
+        w.lock_bottle_to_color(1, Color::Blue);
+
+        assert_eq!(w.try_pour(0, 1), Ok(1));
+        assert_eq!(w.bottles[0].top_color(), Some(Color::Red));
+    }
+
+    #[test]
+    fn move_generation_skips_pours_into_locked_bottles() {
+        let w = level();
+
+        let moves = w.possible_moves(&PruningRules::none());
+
+        assert!(!moves.contains(&Pour::new(0, 2)));
+        assert!(moves.contains(&Pour::new(1, 2)));
+    }
+
+    #[test]
+    fn solver_respects_color_locks() {
+        let mut w = level();
+
+        let solution = WaterSolver::new(&w).solve_configured().0;
+
+        assert!(!solution.is_empty());
+        solution.iter().for_each(|p| assert!(w.try_pour(p.from, p.to).is_ok()));
+        assert!(w.win());
+    }
+
+    #[test]
+    fn color_locks_are_serialized_per_bottle() {
+        let mut w = level();
+
+        w.bottle_color_locks();
+
+        assert_eq!(w.locks_serialized, [0, 0, WaterSorting::map_color_to_u8(Some(Color::Blue))]);
+    }
 }
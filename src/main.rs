@@ -1,24 +1,232 @@
 use std::io;
-use water_sort::{Color, WaterSolver, WaterSorting};
+use water_sort::WaterSolver;
+use water_sort::evolution::{evolve, EvolutionParams};
+use water_sort::generator::{generate, GeneratorParams};
+use water_sort::campaign::Campaign;
+use water_sort::duplicates::find_duplicates;
+use water_sort::pack::LevelPack;
+use water_sort::rules::RuleSet;
+use water_sort::scoring::StarThresholds;
+use water_sort::strategy::SolverConfig;
+use water_sort::timer::TimerConfig;
+
+fn solver_config(args: &[String]) -> SolverConfig {
+    let mut config = SolverConfig::new();
+    for arg in args {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+        match name {
+            "--algorithm" => config.algorithm = value.parse().unwrap_or_else(|e| panic!("{}", e)),
+            "--max-depth" => config.max_depth = value.parse().expect("--max-depth expects a number"),
+            "--max-nodes" => config.max_nodes = value.parse().expect("--max-nodes expects a number"),
+            "--beam-width" => config.beam_width = value.parse().expect("--beam-width expects a number"),
+            "--optimal" => config.require_optimal = true,
+            _ => {}
+        }
+    }
+    config
+}
+
+fn generator_params(args: &[String]) -> Option<GeneratorParams> {
+    if !args.iter().any(|arg| arg == "--generate" || arg == "--evolve") {
+        return None;
+    }
+    let mut params = GeneratorParams::new(7, 2, 4, 0);
+    for arg in args {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+        match name {
+            "--colors" => params.colors = value.parse().expect("--colors expects a number"),
+            "--empty" => params.empty_bottles = value.parse().expect("--empty expects a number"),
+            "--capacity" => params.capacity = value.parse().expect("--capacity expects a number"),
+            "--seed" => params.seed = value.parse().expect("--seed expects a number"),
+            "--attempts" => params.max_attempts = value.parse().expect("--attempts expects a number"),
+            "--difficulty" => {
+                let (min, max) = value.split_once('-').expect("--difficulty expects a range like 40-60");
+                params.min_difficulty = min.parse().expect("--difficulty expects numbers");
+                params.max_difficulty = max.parse().expect("--difficulty expects numbers");
+            }
+            "--no-solved" => params.constraints.no_solved_bottles = true,
+            "--max-run" => params.constraints.max_run_length = value.parse().expect("--max-run expects a number"),
+            "--min-distinct" => params.constraints.min_distinct_colors = value.parse().expect("--min-distinct expects a number"),
+            "--required-empty" => params.constraints.required_empty_bottles = Some(value.parse().expect("--required-empty expects a number")),
+            _ => {}
+        }
+    }
+    Some(params)
+}
+
+fn evolution_params(args: &[String]) -> Option<EvolutionParams> {
+    if !args.iter().any(|arg| arg == "--evolve") {
+        return None;
+    }
+    let mut params = EvolutionParams::new(generator_params(args)?, 20, 30);
+    for arg in args {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+        match name {
+            "--population" => params.population = value.parse().expect("--population expects a number"),
+            "--generations" => params.generations = value.parse().expect("--generations expects a number"),
+            "--dead-end-weight" => params.dead_end_weight = value.parse().expect("--dead-end-weight expects a number"),
+            _ => {}
+        }
+    }
+    Some(params)
+}
+
+fn run_evolution(params: &EvolutionParams) {
+    let evolution = match evolve(params) {
+        Ok(evolution) => evolution,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    for summary in &evolution.generations {
+        println!("Generation {:>3}: best {:.2}, mean {:.2}, {} new", summary.generation, summary.best_fitness, summary.mean_fitness, summary.accepted);
+    }
+    for &id in &evolution.hall_of_fame {
+        let individual = &evolution.individuals[id];
+        let lineage = evolution.lineage(id).iter().map(|i| i.id.to_string()).collect::<Vec<_>>();
+        println!();
+        println!("#{} fitness {:.2} (generation {}, lineage {})", id, individual.fitness, individual.generation, lineage.join(" -> "));
+        println!("{}", individual.level);
+        println!("{}", individual.rating);
+    }
+}
+
+fn save_path(args: &[String]) -> Option<&str> {
+    args.iter().find_map(|arg| arg.strip_prefix("--save="))
+}
+
+// the level is picked with --level=ID, otherwise it is the next one to solve
+fn open_campaign(args: &[String], path: &str) -> Result<(Campaign, usize), String> {
+    let mut campaign = Campaign::new(LevelPack::load(path).map_err(|e| e.to_string())?);
+    if let Some(save) = save_path(args) {
+        campaign.load_file(save).map_err(|e| e.to_string())?;
+    }
+    let index = match args.iter().find_map(|arg| arg.strip_prefix("--level=")) {
+        Some(id) => campaign.pack().levels.iter().position(|level| level.id == id).ok_or_else(|| format!("there is no level {} in the pack", id))?,
+        None => campaign.next_level().unwrap_or(0),
+    };
+    if campaign.level(index).is_none() {
+        return Err(format!("level {} cannot be played", index + 1));
+    }
+    if !campaign.is_unlocked(index) {
+        return Err(format!("level {} is still locked", campaign.pack().levels[index].id));
+    }
+    Ok((campaign, index))
+}
 
 pub fn main() {
-    let mut w = WaterSorting::new();
-    w.init_bottle_with_four_colors(Color::Red, Color::Magenta, Color::Magenta, Color::Orange);
-    w.init_bottle_with_four_colors(Color::Yellow, Color::Brown, Color::Blue, Color::Green);
-    w.init_bottle_with_four_colors(Color::Brown, Color::Red, Color::Orange, Color::Red);
-    w.init_bottle_with_four_colors(Color::Brown, Color::Blue, Color::Blue, Color::Orange);
-    w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Orange, Color::Yellow);
-    w.init_bottle_with_four_colors(Color::Red, Color::Yellow, Color::Magenta, Color::Magenta);
-    w.init_bottle_with_four_colors(Color::Blue, Color::Green, Color::Brown, Color::Yellow);
-    w.init_empty_bottle();
-    w.init_empty_bottle();
+    let args = std::env::args().collect::<Vec<_>>();
+    let show_stats = args.iter().any(|arg| arg == "--stats");
+    let config = solver_config(&args);
+    if let Some(params) = evolution_params(&args) {
+        run_evolution(&params);
+        return;
+    }
+    if let Some(paths) = args.iter().find_map(|arg| arg.strip_prefix("--duplicates=")) {
+        match paths.split(',').map(LevelPack::load).collect::<Result<Vec<_>, _>>() {
+            Ok(packs) => print!("{}", find_duplicates(&packs)),
+            Err(e) => println!("{}", e),
+        }
+        return;
+    }
+    let classic = LevelPack::classic();
+    let mut w = classic.build_level("console").expect("bundled pack has the console level");
+    let mut par = classic.level("console").and_then(|level| level.par_moves);
+    let mut campaign = None;
+    if let Some(path) = args.iter().find_map(|arg| arg.strip_prefix("--pack=")) {
+        match open_campaign(&args, path) {
+            Ok((opened, index)) => {
+                let info = &opened.pack().levels[index];
+                println!("{} ({}) by {}", info.name, opened.pack().name, opened.pack().level_author(info));
+                w = opened.level(index).unwrap();
+                par = info.par_moves;
+                campaign = Some((opened, index));
+            }
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+    if let Some(params) = generator_params(&args) {
+        match generate(&params) {
+            Ok(generated) => {
+                println!("Generated level after {} candidate(s)", generated.attempts);
+                if let Some(rating) = generated.rating {
+                    println!("{}", rating);
+                }
+                w = generated.level;
+            }
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+    if args.iter().any(|arg| arg == "--strict") {
+        w = w.under_rules(RuleSet::strict());
+    }
+    if let Some(rules) = args.iter().find_map(|arg| arg.strip_prefix("--rules=")) {
+        match rules.parse() {
+            Ok(rules) => w = w.under_rules(rules),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+    if let Some(moves) = args.iter().find_map(|arg| arg.strip_prefix("--max-moves=")) {
+        let max_moves = Some(moves.parse().expect("--max-moves expects a number"));
+        w = w.under_rules(RuleSet { max_moves, ..w.rules() });
+    }
+    if let Some(extra) = args.iter().find_map(|arg| arg.strip_prefix("--extra-bottles=")) {
+        let extra_bottles = extra.parse().expect("--extra-bottles expects a number");
+        w = w.under_rules(RuleSet { extra_bottles, ..w.rules() });
+    }
+    let par = par.or_else(|| WaterSolver::with_config(&w, config).par());
+    if let Some(seconds) = args.iter().find_map(|arg| arg.strip_prefix("--time-limit=")) {
+        let seconds: u64 = seconds.parse().expect("--time-limit expects a number of seconds");
+        w.start_timer(TimerConfig::countdown(seconds * 1000));
+    }
     loop {
         println!("{}", w);
         if w.win() {
             println!("You Won!");
+            if let Some(result) = w.timed_result() {
+                println!("{}", result);
+            }
+            if let Some((campaign, index)) = &mut campaign {
+                match campaign.record_win(*index, &w) {
+                    Ok(Some(rating)) => println!("{}", rating),
+                    Ok(None) => println!("Solved in {} moves, there is no par to award stars against", w.moves_made()),
+                    Err(e) => println!("{}", e),
+                }
+                println!("{} of {} levels solved, {} stars", (0..campaign.level_count()).filter(|&i| campaign.is_solved(i)).count(), campaign.level_count(), campaign.total_stars());
+                if let Some(save) = save_path(&args) {
+                    if let Err(e) = campaign.save_file(save) {
+                        println!("{}", e);
+                    }
+                }
+            } else if let Some(rating) = par.and_then(|par| w.stars(par, &StarThresholds::new())) {
+                println!("{}", rating);
+            }
+            break;
+        }
+        if w.lost() {
+            println!("{}, you lost!", if w.time_up() { "Time is up" } else { "No moves left" });
             break;
         }
         println!();
+        if let Some(left) = w.moves_left() {
+            println!("Moves left: {}", left);
+        }
+        if let Some(left) = w.remaining_ms() {
+            println!("Time left: {}s", left.div_ceil(1000));
+        }
+        if w.extra_bottles_left() > 0 {
+            println!("Extra bottles left: {} (extra or extra half)", w.extra_bottles_left());
+        }
         println!("Provide next move (src -> desc) or solve: ");
         let mut line: String = Default::default();
         let result = io::stdin().read_line(&mut line);
@@ -28,8 +236,11 @@ pub fn main() {
         }
 
         if line.trim_end() == "solve" {
-            let solver = WaterSolver::new(&w);
-            let solution = solver.solve(20);
+            let solver = WaterSolver::with_config(&w, config);
+            let (solution, stats) = solver.solve_configured();
+            if show_stats {
+                println!("{}", stats);
+            }
             println!("Solution: ");
             solution.iter().for_each(|pour| {
                 let from = pour.from;
@@ -38,6 +249,12 @@ pub fn main() {
                 w.pour(from, to);
             } );
 
+        } else if let Some(size) = line.trim_end().strip_prefix("extra") {
+            // a half-size bottle holds half the layers of the largest bottle
+            let capacity = if size.trim() == "half" { w.capacity().div_ceil(2) } else { w.capacity() };
+            if let Err(rejection) = w.try_add_extra_bottle(capacity) {
+                println!("Cannot add a bottle: {}", rejection);
+            }
         } else {
             let moves = line
                 .trim_end()
@@ -52,7 +269,9 @@ pub fn main() {
             let source_no = moves[0] - 1;
             let destination_no = moves[1] - 1;
             println!("Pouring...");
-            w.pour(source_no, destination_no);
+            if let Err(rejection) = w.try_pour(source_no, destination_no) {
+                println!("Cannot pour: {}", rejection);
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use crate::{Pour, WaterSorting};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RemovalReason {
+    // pour did not change the level at all
+    NoOp,
+    // pour was undone by the very next one
+    Reversed,
+    // pour was part of a sequence that came back to an already visited state
+    Cycle,
+    // pour was folded into the previous one, which became `into`; a later removal of `into`
+    // is reported under the index of the previous pour
+    Merged { into: Pour },
+    // level was already won before this pour
+    PastWin,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RemovedPour {
+    pub index: usize,
+    pub pour: Pour,
+    pub reason: RemovalReason,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizationReport {
+    pub original_len: usize,
+    pub optimized_len: usize,
+    pub removed: Vec<RemovedPour>,
+}
+
+/// Replays `solution` on `level` and returns a shorter sequence that reaches the same final state,
+/// together with a report of every original pour that was dropped (indices refer to `solution`).
+/// Every index is reported at most once, so the report holds one entry per pour the solution got shorter by.
+pub fn optimize(level: &WaterSorting, solution: &[Pour]) -> (Vec<Pour>, OptimizationReport) {
+    let mut removed = Vec::new();
+    let mut kept = remove_cycles(level, &solution.iter().copied().enumerate().collect::<Vec<_>>(), &mut removed);
+
+    loop {
+        let merged = merge_chains(level, &kept, &mut removed);
+        let without_cycles = remove_cycles(level, &merged, &mut removed);
+        let changed = without_cycles.len() != kept.len();
+        kept = without_cycles;
+        if !changed {
+            break;
+        }
+    }
+
+    removed.sort_by_key(|r| r.index);
+    let optimized = kept.iter().map(|(_, p)| *p).collect::<Vec<_>>();
+    let report = OptimizationReport {
+        original_len: solution.len(),
+        optimized_len: optimized.len(),
+        removed,
+    };
+    (optimized, report)
+}
+
+fn remove_cycles(level: &WaterSorting, pours: &[(usize, Pour)], removed: &mut Vec<RemovedPour>) -> Vec<(usize, Pour)> {
+    let mut state = level.clone();
+    let mut kept: Vec<(usize, Pour)> = Vec::new();
+    // position in `kept` after which a given state was reached
+    let mut visited: HashMap<WaterSorting, usize> = HashMap::new();
+    visited.insert(state.clone(), 0);
+
+    for (i, &(index, pour)) in pours.iter().enumerate() {
+        if state.win() {
+            removed.extend(pours[i..].iter().map(|&(index, pour)| RemovedPour { index, pour, reason: RemovalReason::PastWin }));
+            break;
+        }
+        state.pour(pour.from, pour.to);
+        match visited.get(&state) {
+            Some(&position) if position == kept.len() => {
+                removed.push(RemovedPour { index, pour, reason: RemovalReason::NoOp });
+            }
+            Some(&position) => {
+                let reason = if kept.len() - position == 1 { RemovalReason::Reversed } else { RemovalReason::Cycle };
+                kept.drain(position..).for_each(|(index, pour)| removed.push(RemovedPour { index, pour, reason }));
+                removed.push(RemovedPour { index, pour, reason });
+                visited.retain(|_, p| *p <= position);
+            }
+            None => {
+                kept.push((index, pour));
+                visited.insert(state.clone(), kept.len());
+            }
+        }
+    }
+    kept
+}
+
+fn merge_chains(level: &WaterSorting, pours: &[(usize, Pour)], removed: &mut Vec<RemovedPour>) -> Vec<(usize, Pour)> {
+    let mut state = level.clone();
+    let mut kept: Vec<(usize, Pour)> = Vec::new();
+    let mut i = 0;
+    while i < pours.len() {
+        let (index, first) = pours[i];
+        if let Some(&(next_index, second)) = pours.get(i + 1) {
+            let shortcut = Pour::new(first.from, second.to);
+            if shortcut.from != shortcut.to {
+                let mut two_steps = state.clone();
+                two_steps.pour(first.from, first.to);
+                two_steps.pour(second.from, second.to);
+                let mut one_step = state.clone();
+                one_step.pour(shortcut.from, shortcut.to);
+                if one_step == two_steps {
+                    removed.push(RemovedPour { index: next_index, pour: second, reason: RemovalReason::Merged { into: shortcut } });
+                    kept.push((index, shortcut));
+                    state = one_step;
+                    i += 2;
+                    continue;
+                }
+            }
+        }
+        state.pour(first.from, first.to);
+        kept.push((index, first));
+        i += 1;
+    }
+    kept
+}
+
+#[cfg(test)]
+mod optimize_tests {
+    use crate::{Color, Pour, WaterSolver, WaterSorting};
+    use crate::optimizer::{optimize, RemovalReason};
+
+    fn level() -> WaterSorting {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Green, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Green);
+        w.init_empty_bottle();
+        w
+    }
+
+    fn replay(level: &WaterSorting, pours: &[Pour]) -> WaterSorting {
+        let mut w = level.clone();
+        pours.iter().for_each(|p| w.pour(p.from, p.to));
+        w
+    }
+
+    #[test]
+    fn already_optimal_solution_is_not_changed() {
+        let w = level();
+        let solution = [Pour::new(0, 2), Pour::new(1, 0), Pour::new(1, 2)].to_vec();
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert_eq!(optimized, solution);
+        assert!(report.removed.is_empty());
+        assert_eq!(report.original_len, 3);
+        assert_eq!(report.optimized_len, 3);
+    }
+
+    #[test]
+    fn immediately_reversed_pour_is_removed() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_two_colors(Color::Red, Color::Red);
+        w.init_bottle_with_two_colors(Color::Red, Color::Red);
+        w.init_empty_bottle();
+        let solution = [Pour::new(0, 2), Pour::new(2, 0), Pour::new(0, 1)].to_vec();
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert_eq!(optimized, [Pour::new(0, 1)].to_vec());
+        assert_eq!(report.removed.len(), 2);
+        assert!(report.removed.iter().all(|r| r.reason == RemovalReason::Reversed));
+        assert!(replay(&w, &optimized).win());
+    }
+
+    #[test]
+    fn pour_that_does_nothing_is_removed() {
+        let w = level();
+        let solution = [Pour::new(0, 1), Pour::new(0, 2), Pour::new(1, 0), Pour::new(1, 2)].to_vec();
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert_eq!(optimized.len(), 3);
+        assert_eq!(report.removed[0].index, 0);
+        assert_eq!(report.removed[0].reason, RemovalReason::NoOp);
+    }
+
+    #[test]
+    fn longer_cycle_is_cut() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_two_colors(Color::Red, Color::Red);
+        w.init_bottle_with_two_colors(Color::Red, Color::Red);
+        w.init_empty_bottle();
+        w.init_empty_bottle();
+        let solution = [Pour::new(0, 2), Pour::new(2, 3), Pour::new(3, 0), Pour::new(0, 1)].to_vec();
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert_eq!(optimized, [Pour::new(0, 1)].to_vec());
+        assert_eq!(report.removed.iter().filter(|r| r.reason == RemovalReason::Cycle).count(), 3);
+        assert!(replay(&w, &optimized).win());
+    }
+
+    #[test]
+    fn chain_through_intermediate_bottle_is_merged() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_two_colors(Color::Red, Color::Blue);
+        w.init_bottle_with_one_color(Color::Red);
+        w.init_bottle_with_one_color(Color::Blue);
+        w.init_empty_bottle();
+        let solution = [Pour::new(0, 3), Pour::new(3, 2), Pour::new(0, 1)].to_vec();
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert_eq!(optimized, [Pour::new(0, 2), Pour::new(0, 1)].to_vec());
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.removed[0].index, 1);
+        assert_eq!(report.removed[0].reason, RemovalReason::Merged { into: Pour::new(0, 2) });
+        assert_eq!(replay(&w, &optimized), replay(&w, &solution));
+    }
+
+    #[test]
+    fn merged_pour_can_be_merged_again() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_two_colors(Color::Red, Color::Blue);
+        w.init_bottle_with_one_color(Color::Red);
+        w.init_bottle_with_one_color(Color::Blue);
+        w.init_empty_bottle();
+        w.init_empty_bottle();
+        let solution = [Pour::new(0, 3), Pour::new(3, 4), Pour::new(4, 2), Pour::new(0, 1)].to_vec();
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert_eq!(optimized, [Pour::new(0, 2), Pour::new(0, 1)].to_vec());
+        assert_eq!(report.removed.iter().map(|r| r.index).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(report.removed.len(), solution.len() - optimized.len());
+        assert_eq!(replay(&w, &optimized), replay(&w, &solution));
+    }
+
+    #[test]
+    fn merge_followed_by_a_cut_reports_every_index_once() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_two_colors(Color::Red, Color::Blue);
+        w.init_bottle_with_one_color(Color::Red);
+        w.init_bottle_with_one_color(Color::Blue);
+        w.init_empty_bottle();
+        let solution = [Pour::new(0, 3), Pour::new(3, 2), Pour::new(1, 3), Pour::new(3, 1), Pour::new(0, 1)].to_vec();
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert_eq!(optimized, [Pour::new(0, 2), Pour::new(0, 1)].to_vec());
+        let reasons = report.removed.iter().map(|r| (r.index, r.reason)).collect::<Vec<_>>();
+        assert_eq!(reasons, [
+            (1, RemovalReason::Merged { into: Pour::new(0, 2) }),
+            (2, RemovalReason::Reversed),
+            (3, RemovalReason::Reversed),
+        ]);
+        assert_eq!(report.removed.len(), solution.len() - optimized.len());
+        assert_eq!(replay(&w, &optimized), replay(&w, &solution));
+    }
+
+    #[test]
+    fn pours_after_the_win_are_removed() {
+        let w = level();
+        let solution = [Pour::new(0, 2), Pour::new(1, 0), Pour::new(1, 2), Pour::new(0, 1)].to_vec();
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert_eq!(optimized.len(), 3);
+        assert_eq!(report.removed[0].reason, RemovalReason::PastWin);
+    }
+
+    #[test]
+    fn optimized_solver_result_still_wins() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Yellow, Color::Magenta, Color::Brown, Color::Yellow);
+        w.init_bottle_with_four_colors(Color::Magenta, Color::Magenta, Color::Brown, Color::Yellow);
+        w.init_bottle_with_four_colors(Color::Brown, Color::Brown, Color::Yellow, Color::Magenta);
+        w.init_empty_bottle();
+        w.init_empty_bottle();
+        let solution = WaterSolver::new(&w).solve(10);
+
+        let (optimized, report) = optimize(&w, &solution);
+
+        assert!(optimized.len() <= solution.len());
+        assert_eq!(report.removed.len(), solution.len() - optimized.len());
+        assert!(replay(&w, &optimized).win());
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+use crate::{Color, WaterSorting, COLORS, MAX_CAPACITY};
+use crate::frozen::ThawTrigger;
+use crate::rules::RuleSet;
+
+// first line of every pack file, followed by the format version
+const PACK_HEADER: &str = "water-sort-pack";
+pub const PACK_FORMAT_VERSION: u32 = 1;
+
+// the levels the console and web versions start with
+pub const CLASSIC_PACK: &str = include_str!("../levels/classic.pack");
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PackError {
+    Io(String),
+    UnsupportedVersion(u32),
+    Syntax { line: usize, message: String },
+    DuplicateId(String),
+    UnknownLevel(String),
+    InvalidLevel { id: String, reason: String },
+}
+
+impl Display for PackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackError::Io(reason) => write!(f, "cannot access pack: {}", reason),
+            PackError::UnsupportedVersion(version) =>
+                write!(f, "pack format version {} is not supported (expected at most {})", version, PACK_FORMAT_VERSION),
+            PackError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            PackError::DuplicateId(id) => write!(f, "level id {} is used more than once", id),
+            PackError::UnknownLevel(id) => write!(f, "there is no level {} in the pack", id),
+            PackError::InvalidLevel { id, reason } => write!(f, "level {} is invalid: {}", id, reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackLevel {
+    pub id: String,
+    pub name: String,
+    // falls back to the pack author when missing
+    pub author: Option<String>,
+    // size of every bottle unless `capacities` gives another
+    pub capacity: usize,
+    // moves of the reference solution
+    pub par_moves: Option<usize>,
+    // `rate_difficulty` score, 0..100
+    pub difficulty: Option<f64>,
+    pub tags: Vec<String>,
+    pub rules: RuleSet,
+    // layers of every bottle, bottom first, empty for an empty bottle
+    pub bottles: Vec<Vec<Color>>,
+    // bottles of another size than `capacity`, by bottle index
+    pub capacities: Vec<(usize, usize)>,
+    // bottles that only take one color, by bottle index
+    pub locks: Vec<(usize, Color)>,
+    // frozen layers as bottle index and layer, bottom first
+    pub frozen: Vec<(usize, usize)>,
+    pub thaw: ThawTrigger,
+}
+
+impl PackLevel {
+    pub fn new(id: &str, name: &str, level: &WaterSorting) -> Self {
+        PackLevel {
+            id: id.to_string(),
+            name: name.to_string(),
+            author: None,
+            capacity: level.capacity(),
+            par_moves: None,
+            difficulty: None,
+            tags: Vec::new(),
+            rules: level.rules(),
+            bottles: level.bottles.iter().map(|b| b.layers().iter().flatten().copied().collect()).collect(),
+            capacities: level.bottles.iter().enumerate().filter(|(_, b)| b.capacity != level.capacity()).map(|(i, b)| (i, b.capacity)).collect(),
+            locks: level.bottles.iter().enumerate().filter_map(|(i, b)| Some((i, b.color_lock?))).collect(),
+            frozen: level.bottles.iter().enumerate()
+                .flat_map(|(i, b)| b.frozen.iter().enumerate().filter(|(_, &frozen)| frozen).map(move |(layer, _)| (i, layer)))
+                .collect(),
+            thaw: level.thaw_trigger(),
+        }
+    }
+
+    pub fn bottle_capacity(&self, bottle: usize) -> usize {
+        self.capacities.iter().find(|(i, _)| *i == bottle).map_or(self.capacity, |&(_, capacity)| capacity)
+    }
+
+    pub fn build(&self) -> Result<WaterSorting, PackError> {
+        let invalid = |reason: String| PackError::InvalidLevel { id: self.id.clone(), reason };
+        if self.capacity == 0 || self.capacity > MAX_CAPACITY || self.capacities.iter().any(|&(_, capacity)| capacity == 0 || capacity > MAX_CAPACITY) {
+            return Err(invalid(format!("capacity must be between 1 and {}", MAX_CAPACITY)));
+        }
+        if let Some(bottle) = (0..self.bottles.len()).find(|&bottle| self.bottles[bottle].len() > self.bottle_capacity(bottle)) {
+            return Err(invalid(format!("bottle {} holds more than {} layers", bottle + 1, self.bottle_capacity(bottle))));
+        }
+        if let Some(&(bottle, _)) = self.locks.iter().find(|(bottle, _)| *bottle >= self.bottles.len()) {
+            return Err(invalid(format!("bottle {} cannot be locked, there is no such bottle", bottle + 1)));
+        }
+        if let Some(&(bottle, layer)) = self.frozen.iter().find(|&&(bottle, layer)| self.bottles.get(bottle).map_or(true, |colors| layer >= colors.len())) {
+            return Err(invalid(format!("layer {} of bottle {} cannot be frozen, there is no such layer", layer + 1, bottle + 1)));
+        }
+        let mut level = WaterSorting::with_rules(self.rules);
+        self.bottles.iter().enumerate().for_each(|(bottle, colors)| level.init_bottle_with_colors(self.bottle_capacity(bottle), colors));
+        self.locks.iter().for_each(|&(bottle, color)| level.lock_bottle_to_color(bottle, color));
+        self.frozen.iter().for_each(|&(bottle, layer)| { level.freeze_layer(bottle, layer); });
+        level.set_thaw_trigger(self.thaw);
+        Ok(level)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelPack {
+    pub version: u32,
+    pub name: String,
+    pub author: String,
+    pub description: String,
+    pub levels: Vec<PackLevel>,
+}
+
+impl LevelPack {
+    pub fn new(name: &str, author: &str) -> Self {
+        LevelPack {
+            version: PACK_FORMAT_VERSION,
+            name: name.to_string(),
+            author: author.to_string(),
+            description: String::new(),
+            levels: Vec::new(),
+        }
+    }
+
+    pub fn level(&self, id: &str) -> Option<&PackLevel> {
+        self.levels.iter().find(|level| level.id == id)
+    }
+
+    pub fn build_level(&self, id: &str) -> Result<WaterSorting, PackError> {
+        self.level(id).ok_or_else(|| PackError::UnknownLevel(id.to_string()))?.build()
+    }
+
+    pub fn level_author<'a>(&'a self, level: &'a PackLevel) -> &'a str {
+        level.author.as_deref().unwrap_or(&self.author)
+    }
+
+    /// The pack bundled with the game, see `CLASSIC_PACK`.
+    pub fn classic() -> LevelPack {
+        CLASSIC_PACK.parse().expect("bundled pack is valid")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<LevelPack, PackError> {
+        std::fs::read_to_string(path).map_err(|e| PackError::Io(e.to_string()))?.parse()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PackError> {
+        std::fs::write(path, self.to_string()).map_err(|e| PackError::Io(e.to_string()))
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::Empty => "empty",
+        Color::Blue => "blue",
+        Color::Red => "red",
+        Color::Gray => "gray",
+        Color::Orange => "orange",
+        Color::Brown => "brown",
+        Color::Yellow => "yellow",
+        Color::Green => "green",
+        Color::Magenta => "magenta",
+        Color::Teal => "teal",
+        Color::Purple => "purple",
+        Color::LightBlue => "lightblue",
+        Color::Peach => "peach",
+        Color::Olive => "olive",
+        Color::Rainbow => "rainbow",
+    }
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    COLORS.iter().copied().chain([Color::Rainbow]).find(|&c| color_name(c) == name.to_lowercase())
+}
+
+// values are single lines, so line breaks are flattened when writing
+fn single_line(value: &str) -> String {
+    value.lines().map(str::trim).collect::<Vec<_>>().join(" ")
+}
+
+impl Display for LevelPack {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} {}", PACK_HEADER, self.version)?;
+        writeln!(f, "name = {}", single_line(&self.name))?;
+        writeln!(f, "author = {}", single_line(&self.author))?;
+        if !self.description.is_empty() {
+            writeln!(f, "description = {}", single_line(&self.description))?;
+        }
+        for level in &self.levels {
+            writeln!(f)?;
+            writeln!(f, "[level]")?;
+            writeln!(f, "id = {}", single_line(&level.id))?;
+            writeln!(f, "name = {}", single_line(&level.name))?;
+            if let Some(author) = &level.author {
+                writeln!(f, "author = {}", single_line(author))?;
+            }
+            writeln!(f, "capacity = {}", level.capacity)?;
+            if let Some(par) = level.par_moves {
+                writeln!(f, "par = {}", par)?;
+            }
+            if let Some(difficulty) = level.difficulty {
+                writeln!(f, "difficulty = {}", difficulty)?;
+            }
+            if !level.tags.is_empty() {
+                writeln!(f, "tags = {}", level.tags.iter().map(|tag| single_line(tag)).collect::<Vec<_>>().join(", "))?;
+            }
+            if level.rules != RuleSet::water() {
+                writeln!(f, "rules = {}", level.rules)?;
+            }
+            if level.thaw != ThawTrigger::default() {
+                writeln!(f, "thaw = {}", level.thaw)?;
+            }
+            for (i, bottle) in level.bottles.iter().enumerate() {
+                let mut colors = bottle.iter().map(|&c| color_name(c).to_string()).collect::<Vec<_>>();
+                if level.bottle_capacity(i) != level.capacity {
+                    colors.push(format!("/{}", level.bottle_capacity(i)));
+                }
+                writeln!(f, "bottle = {}", colors.join(" "))?;
+                if let Some((_, color)) = level.locks.iter().find(|(bottle, _)| *bottle == i) {
+                    writeln!(f, "lock = {}", color_name(*color))?;
+                }
+                let frozen = level.frozen.iter().filter(|(bottle, _)| *bottle == i).map(|(_, layer)| (layer + 1).to_string()).collect::<Vec<_>>();
+                if !frozen.is_empty() {
+                    writeln!(f, "frozen = {}", frozen.join(" "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for LevelPack {
+    type Err = PackError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let syntax = |line: usize, message: String| PackError::Syntax { line, message };
+
+        let (line, header) = lines.next().ok_or_else(|| syntax(1, "empty pack".to_string()))?;
+        let version = header.strip_prefix(PACK_HEADER)
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or_else(|| syntax(line, format!("expected `{} <version>`", PACK_HEADER)))?;
+        if version == 0 || version > PACK_FORMAT_VERSION {
+            return Err(PackError::UnsupportedVersion(version));
+        }
+
+        let mut pack = LevelPack { version, ..LevelPack::new("", "") };
+        let mut ids = HashSet::new();
+        for (line, text) in lines {
+            if text == "[level]" {
+                pack.levels.push(PackLevel {
+                    id: String::new(),
+                    name: String::new(),
+                    author: None,
+                    capacity: MAX_CAPACITY,
+                    par_moves: None,
+                    difficulty: None,
+                    tags: Vec::new(),
+                    rules: RuleSet::water(),
+                    bottles: Vec::new(),
+                    capacities: Vec::new(),
+                    locks: Vec::new(),
+                    frozen: Vec::new(),
+                    thaw: ThawTrigger::default(),
+                });
+                continue;
+            }
+            let (key, value) = text.split_once('=').ok_or_else(|| syntax(line, format!("expected `key = value`, got `{}`", text)))?;
+            let (key, value) = (key.trim(), value.trim());
+            let number = |what: &str| syntax(line, format!("{} expects a number, got `{}`", what, value));
+            match (pack.levels.last_mut(), key) {
+                (None, "name") => pack.name = value.to_string(),
+                (None, "author") => pack.author = value.to_string(),
+                (None, "description") => pack.description = value.to_string(),
+                (Some(level), "id") => level.id = value.to_string(),
+                (Some(level), "name") => level.name = value.to_string(),
+                (Some(level), "author") => level.author = Some(value.to_string()),
+                (Some(level), "capacity") => level.capacity = value.parse().map_err(|_| number(key))?,
+                (Some(level), "par") => level.par_moves = Some(value.parse().map_err(|_| number(key))?),
+                (Some(level), "difficulty") => level.difficulty = Some(value.parse().map_err(|_| number(key))?),
+                (Some(level), "tags") => level.tags = value.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect(),
+                (Some(level), "rules") => level.rules = value.parse().map_err(|e| syntax(line, e))?,
+                (Some(level), "thaw") => level.thaw = value.parse().map_err(|e| syntax(line, e))?,
+                // an optional `/N` at the end sets the size of this bottle
+                (Some(level), "bottle") => {
+                    let (value, capacity) = match value.rsplit_once('/') {
+                        Some((colors, capacity)) => (colors, Some(capacity.trim().parse().map_err(|_| number("bottle size"))?)),
+                        None => (value, None),
+                    };
+                    if let Some(capacity) = capacity {
+                        level.capacities.push((level.bottles.len(), capacity));
+                    }
+                    let colors = value.split_whitespace()
+                        .map(parse_color)
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| syntax(line, format!("unknown color in `{}`", value)))?;
+                    level.bottles.push(colors);
+                }
+                // locks the bottle of the line before
+                (Some(level), "lock") => {
+                    let color = parse_color(value).ok_or_else(|| syntax(line, format!("unknown color `{}`", value)))?;
+                    let bottle = level.bottles.len().checked_sub(1).ok_or_else(|| syntax(line, "`lock` has to follow a `bottle` line".to_string()))?;
+                    level.locks.push((bottle, color));
+                }
+                // layers of the bottle of the line before, 1 is the bottom
+                (Some(level), "frozen") => {
+                    let bottle = level.bottles.len().checked_sub(1).ok_or_else(|| syntax(line, "`frozen` has to follow a `bottle` line".to_string()))?;
+                    for layer in value.split_whitespace() {
+                        let layer = layer.parse::<usize>().ok().filter(|&layer| layer > 0).ok_or_else(|| number(key))?;
+                        level.frozen.push((bottle, layer - 1));
+                    }
+                }
+                _ => return Err(syntax(line, format!("unexpected key `{}`", key))),
+            }
+        }
+
+        for level in &pack.levels {
+            if level.id.is_empty() {
+                return Err(PackError::InvalidLevel { id: level.name.clone(), reason: "missing id".to_string() });
+            }
+            if !ids.insert(level.id.as_str()) {
+                return Err(PackError::DuplicateId(level.id.clone()));
+            }
+            level.build()?;
+        }
+        Ok(pack)
+    }
+}
+
+#[wasm_bindgen]
+pub fn pack_level_count(text: &str) -> usize {
+    text.parse::<LevelPack>().map_or(0, |pack| pack.levels.len())
+}
+
+/// Builds the `index`-th level of a pack given as text, `None` when the pack or index is invalid.
+#[wasm_bindgen]
+pub fn pack_level(text: &str, index: usize) -> Option<WaterSorting> {
+    let pack = text.parse::<LevelPack>().ok()?;
+    pack.levels.get(index)?.build().ok()
+}
+
+/// Builds the level with `id` of the bundled classic pack, `None` when there is no such level.
+#[wasm_bindgen]
+pub fn classic_level(id: &str) -> Option<WaterSorting> {
+    LevelPack::classic().build_level(id).ok()
+}
+
+/// Par recorded for the level with `id` of the bundled classic pack, `None` when it has none.
+#[wasm_bindgen]
+pub fn classic_level_par(id: &str) -> Option<usize> {
+    LevelPack::classic().level(id)?.par_moves
+}
+
+#[cfg(test)]
+mod pack_tests {
+    use crate::{Color, WaterSolver, WaterSorting};
+    use crate::analysis::rate_difficulty;
+    use crate::pack::{classic_level, classic_level_par, pack_level, pack_level_count, LevelPack, PackError, PackLevel};
+    use crate::rules::RuleSet;
+
+    const PACK: &str = "# a comment
+water-sort-pack 1
+name = Starter
+author = pawlos
+description = First levels
+
+[level]
+id = intro-1
+name = Warm up
+capacity = 3
+par = 2
+difficulty = 12.5
+tags = easy, intro
+bottle = red red blue
+bottle = blue blue red
+bottle =
+
+[level]
+id = intro-2
+name = Second
+author = guest
+bottle = green green green red
+bottle = red red red green
+bottle =
+";
+
+    #[test]
+    fn pack_is_parsed_with_all_metadata() {
+        let pack = PACK.parse::<LevelPack>().unwrap();
+
+        assert_eq!(pack.version, 1);
+        assert_eq!((pack.name.as_str(), pack.author.as_str(), pack.description.as_str()), ("Starter", "pawlos", "First levels"));
+        assert_eq!(pack.levels.len(), 2);
+        let first = &pack.levels[0];
+        assert_eq!((first.id.as_str(), first.name.as_str(), first.capacity), ("intro-1", "Warm up", 3));
+        assert_eq!((first.par_moves, first.difficulty), (Some(2), Some(12.5)));
+        assert_eq!(first.tags, ["easy", "intro"]);
+        assert_eq!(pack.level_author(first), "pawlos");
+        assert_eq!(pack.level_author(pack.level("intro-2").unwrap()), "guest");
+        assert_eq!(pack.levels[1].capacity, 4);
+        assert_eq!(pack.build_level("intro-3"), Err(PackError::UnknownLevel("intro-3".to_string())));
+    }
+
+    #[test]
+    fn pack_levels_build_playable_games() {
+        let pack = PACK.parse::<LevelPack>().unwrap();
+        let mut w = pack.levels[0].build().unwrap();
+
+        assert_eq!(w.bottles_count(), 3);
+        assert_eq!(w.capacity(), 3);
+        assert!(w.can_be_sorted());
+        w.pour(0, 2);
+        w.pour(1, 0);
+        w.pour(2, 1);
+        assert!(w.win());
+    }
+
+    #[test]
+    fn bottles_of_mixed_sizes_read_back_the_same() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Blue);
+        w.init_bottle_with_colors(3, &[Color::Blue, Color::Blue, Color::Red]);
+        w.init_bottle_with_colors(3, &[Color::Blue]);
+        w.init_empty_bottle_with_capacity(2);
+        let mut pack = LevelPack::new("Mixed", "me");
+        pack.levels.push(PackLevel::new("m", "M", &w));
+
+        let text = pack.to_string();
+        let read = text.parse::<LevelPack>().unwrap();
+
+        assert!(text.contains("bottle = blue blue red /3\n"));
+        assert!(text.contains("bottle = /2\n"));
+        assert_eq!(read.levels, pack.levels);
+        let rebuilt = read.levels[0].build().unwrap();
+        assert_eq!(rebuilt, w);
+        assert_eq!((0..4).map(|i| read.levels[0].bottle_capacity(i)).collect::<Vec<_>>(), [4, 3, 3, 2]);
+    }
+
+    #[test]
+    fn bottle_size_is_checked() {
+        let pack = |bottle: &str| format!("water-sort-pack 1\nname = P\nauthor = a\n[level]\nid = x\nbottle = {}\n", bottle).parse::<LevelPack>();
+
+        assert!(matches!(pack("red red /1"), Err(PackError::InvalidLevel { .. })));
+        assert!(matches!(pack("red /9"), Err(PackError::InvalidLevel { .. })));
+        assert!(matches!(pack("red /x"), Err(PackError::Syntax { .. })));
+    }
+
+    #[test]
+    fn written_pack_reads_back_the_same() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::LightBlue, Color::Olive, Color::LightBlue, Color::Olive);
+        w.init_bottle_with_four_colors(Color::Olive, Color::LightBlue, Color::Olive, Color::LightBlue);
+        w.init_empty_bottle();
+        w.lock_bottle_to_color(2, Color::Olive);
+        w.freeze_layer(0, 0);
+        w.freeze_layer(0, 2);
+        w.thaw_after_moves(3);
+        let mut pack = LevelPack::new("Mine", "me");
+        pack.description = "two\nlines".to_string();
+        pack.levels.push(PackLevel { par_moves: Some(9), tags: vec!["hard".to_string()], ..PackLevel::new("a", "A", &w) });
+        let ball_sort = WaterSorting::with_rules(RuleSet { lock_completed: true, ..RuleSet::ball_sort() });
+        pack.levels.push(PackLevel::new("b", "B", &ball_sort));
+
+        let read = pack.to_string().parse::<LevelPack>().unwrap();
+
+        assert_eq!(read.description, "two lines");
+        assert_eq!(read.levels, pack.levels);
+        assert_eq!(read.levels[0].build().unwrap(), w);
+        assert_eq!(read.levels[0].build().unwrap().bottle_color_lock(2), Some(Color::Olive));
+        assert!(read.levels[0].build().unwrap().is_layer_frozen(0, 2));
+        assert!(pack.to_string().contains("frozen = 1 3\n"));
+        assert_eq!(read.levels[1].build().unwrap().rules(), ball_sort.rules());
+    }
+
+    #[test]
+    fn newer_format_version_is_rejected() {
+        assert_eq!("water-sort-pack 2\nname = x".parse::<LevelPack>(), Err(PackError::UnsupportedVersion(2)));
+        assert!(matches!("name = x".parse::<LevelPack>(), Err(PackError::Syntax { line: 1, .. })));
+    }
+
+    #[test]
+    fn malformed_lines_report_their_line_number() {
+        let unknown_color = PACK.replace("bottle = red red blue", "bottle = red pink blue");
+        let bad_par = PACK.replace("par = 2", "par = two");
+
+        assert!(matches!(unknown_color.parse::<LevelPack>(), Err(PackError::Syntax { line: 14, .. })));
+        assert!(matches!(bad_par.parse::<LevelPack>(), Err(PackError::Syntax { line: 11, .. })));
+    }
+
+    #[test]
+    fn invalid_levels_are_rejected() {
+        let duplicate = PACK.replace("id = intro-2", "id = intro-1");
+        let overfilled = PACK.replace("bottle = red red blue", "bottle = red red blue blue");
+
+        assert_eq!(duplicate.parse::<LevelPack>(), Err(PackError::DuplicateId("intro-1".to_string())));
+        assert!(matches!(overfilled.parse::<LevelPack>(), Err(PackError::InvalidLevel { .. })));
+    }
+
+    #[test]
+    fn wasm_helpers_build_levels_from_text() {
+        assert_eq!(pack_level_count(PACK), 2);
+        assert_eq!(pack_level(PACK, 1).unwrap().bottles_count(), 3);
+        assert!(pack_level(PACK, 2).is_none());
+        assert_eq!(pack_level_count("garbage"), 0);
+    }
+
+    #[test]
+    fn bundled_classic_pack_is_valid() {
+        let pack = LevelPack::classic();
+
+        assert!(pack.levels.iter().all(|level| level.build().unwrap().can_be_sorted()));
+        assert_eq!(classic_level("console").unwrap().bottles_count(), 9);
+        assert_eq!(classic_level("web").unwrap().bottles_count(), 7);
+        assert!(classic_level("missing").is_none());
+        assert_eq!(classic_level_par("web"), pack.level("web").unwrap().par_moves);
+        assert!(classic_level_par("missing").is_none());
+    }
+
+    #[test]
+    fn bundled_par_and_difficulty_match_the_solver_and_rater() {
+        for level in LevelPack::classic().levels {
+            let w = level.build().unwrap();
+            let rating = rate_difficulty(&w, usize::MAX);
+
+            assert_eq!(level.par_moves, WaterSolver::new(&w).par(), "par of {}", level.id);
+            assert_eq!(level.par_moves, Some(rating.optimal_length), "par of {}", level.id);
+            let difficulty = level.difficulty.unwrap();
+            assert!((difficulty - rating.score).abs() < 0.05, "difficulty of {} is {:.1}, not {}", level.id, rating.score, difficulty);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt::{Display, Formatter};
+use wasm_bindgen::prelude::*;
+use crate::{Bottle, WaterSorting};
+
+/// Why `WaterSorting::try_add_extra_bottle` refused to add a bottle.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ExtraBottleRejection {
+    // the rules allow no more extra bottles
+    NoneLeft,
+    // an extra bottle holds at least one layer and no more than the bottles of the level
+    InvalidCapacity,
+    // the level is already won or lost
+    GameOver,
+}
+
+impl Display for ExtraBottleRejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExtraBottleRejection::NoneLeft => write!(f, "there are no extra bottles left"),
+            ExtraBottleRejection::InvalidCapacity => write!(f, "the level has no bottles of that size"),
+            ExtraBottleRejection::GameOver => write!(f, "the game is over"),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WaterSorting {
+    /// Adds an empty bottle with room for `capacity` layers to the game in progress, `false` when
+    /// it is refused. Undoing it right away takes the bottle back and returns it to the budget.
+    pub fn add_extra_bottle(&mut self, capacity: usize) -> bool {
+        self.try_add_extra_bottle(capacity).is_ok()
+    }
+
+    /// Sets how many extra bottles the level allows, the `extra` rule of packs.
+    pub fn allow_extra_bottles(&mut self, count: usize) {
+        self.rules.extra_bottles = count;
+    }
+
+    /// Extra bottles the rules still allow.
+    pub fn extra_bottles_left(&self) -> usize {
+        self.rules.extra_bottles.saturating_sub(self.extra_bottles_used)
+    }
+
+    pub fn extra_bottles_used(&self) -> usize {
+        self.extra_bottles_used
+    }
+}
+
+impl WaterSorting {
+    /// Adds an extra bottle like `add_extra_bottle`, returning its index or why it was refused.
+    pub fn try_add_extra_bottle(&mut self, capacity: usize) -> Result<usize, ExtraBottleRejection> {
+        if self.win() || self.lost() {
+            return Err(ExtraBottleRejection::GameOver);
+        }
+        if self.extra_bottles_left() == 0 {
+            return Err(ExtraBottleRejection::NoneLeft);
+        }
+        if capacity == 0 || capacity > self.capacity() {
+            return Err(ExtraBottleRejection::InvalidCapacity);
+        }
+        self.old_state = Some(self.bottles.to_vec());
+        let index = self.bottles.len();
+        self.bottles.push(Bottle::with_capacity(index, capacity, &[]));
+        self.extra_bottles_used += 1;
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod powerup_tests {
+    use crate::{Color, WaterSolver, WaterSorting};
+    use crate::powerup::ExtraBottleRejection;
+    use crate::rules::RuleSet;
+
+    // stuck without an empty bottle
+    fn level(extra_bottles: usize) -> WaterSorting {
+        let mut w = WaterSorting::with_rules(RuleSet { extra_bottles, ..RuleSet::water() });
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Blue, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Red, Color::Red);
+        w
+    }
+
+    #[test]
+    fn extra_bottle_is_appended_empty_and_can_be_poured_into() {
+        let mut w = level(1);
+        assert!(!w.move_available());
+
+        assert_eq!(w.try_add_extra_bottle(2), Ok(2));
+
+        assert_eq!(w.bottles_count(), 3);
+        assert_eq!(w.bottles[2].available_empty_space(), 2);
+        assert_eq!(w.extra_bottles_left(), 0);
+        assert_eq!(w.moves_made(), 0);
+        assert_eq!(w.try_pour(0, 2), Ok(2));
+    }
+
+    #[test]
+    fn extra_bottles_are_limited_by_the_rules() {
+        let mut w = level(1);
+
+        assert!(w.add_extra_bottle(4));
+        assert_eq!(w.try_add_extra_bottle(4), Err(ExtraBottleRejection::NoneLeft));
+        assert_eq!(level(0).try_add_extra_bottle(4), Err(ExtraBottleRejection::NoneLeft));
+        assert_eq!(level(1).try_add_extra_bottle(5), Err(ExtraBottleRejection::InvalidCapacity));
+        assert_eq!(level(1).try_add_extra_bottle(0), Err(ExtraBottleRejection::InvalidCapacity));
+        w.allow_extra_bottles(3);
+        assert_eq!(w.extra_bottles_left(), 2);
+    }
+
+    #[test]
+    fn undoing_an_extra_bottle_removes_it_and_gives_it_back() {
+        let mut w = level(1);
+
+        w.add_extra_bottle(4);
+        w.undo();
+
+        assert_eq!(w.bottles_count(), 2);
+        assert_eq!(w.extra_bottles_left(), 1);
+        assert_eq!(w.moves_made(), 0);
+        assert_eq!(w.undos_made(), 1);
+    }
+
+    #[test]
+    fn undoing_a_pour_keeps_the_extra_bottle() {
+        let mut w = level(1);
+
+        w.add_extra_bottle(4);
+        w.pour(0, 2);
+        w.undo();
+
+        assert_eq!(w.bottles_count(), 3);
+        assert_eq!(w.extra_bottles_left(), 0);
+        assert_eq!(w.moves_made(), 0);
+    }
+
+    #[test]
+    fn solver_uses_extra_bottles_of_different_sizes() {
+        let mut w = level(2);
+        w.add_extra_bottle(2);
+        w.add_extra_bottle(4);
+
+        let solution = WaterSolver::new(&w).solve_configured().0;
+
+        assert!(!solution.is_empty());
+        solution.iter().for_each(|p| w.pour(p.from, p.to));
+        assert!(w.win());
+    }
+
+    #[test]
+    fn no_extra_bottle_after_the_game_is_over() {
+        let mut w = level(2);
+        w.add_extra_bottle(4);
+        for (from, to) in [(0, 2), (1, 0), (2, 1)] {
+            w.pour(from, to);
+        }
+
+        assert!(w.win());
+        assert_eq!(w.try_add_extra_bottle(4), Err(ExtraBottleRejection::GameOver));
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+use crate::{Pour, WaterSolver, WaterSorting};
+use crate::strategy::PruningRules;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    // number of distinct reachable states (up to bottle order)
+    pub states: usize,
+    // digest of the sorted hashes of all those states
+    pub digest: u64,
+}
+
+#[wasm_bindgen]
+impl Certificate {
+    pub fn encode(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn decode(s: &str) -> Option<Certificate> {
+        s.parse().ok()
+    }
+}
+
+impl Display for Certificate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{:016x}", self.states, self.digest)
+    }
+}
+
+impl FromStr for Certificate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (states, digest) = s.split_once(':').ok_or_else(|| format!("malformed certificate: {}", s))?;
+        Ok(Certificate {
+            states: states.parse().map_err(|_| format!("malformed state count: {}", states))?,
+            digest: u64::from_str_radix(digest, 16).map_err(|_| format!("malformed digest: {}", digest))?,
+        })
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExhaustiveReport {
+    pub states_explored: usize,
+    // every reachable state was visited, so a missing solution proves the level unsolvable
+    pub closed: bool,
+    solution: Option<Vec<Pour>>,
+    certificate: Option<Certificate>,
+}
+
+#[wasm_bindgen]
+impl ExhaustiveReport {
+    pub fn is_solvable(&self) -> bool {
+        self.solution.is_some()
+    }
+
+    pub fn is_unsolvable(&self) -> bool {
+        self.closed && self.solution.is_none()
+    }
+
+    pub fn certificate(&self) -> Option<Certificate> {
+        self.certificate
+    }
+}
+
+impl ExhaustiveReport {
+    pub fn solution(&self) -> Option<&[Pour]> {
+        self.solution.as_deref()
+    }
+}
+
+// FNV-1a, so digests are the same on every platform and compiler version
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+// solvability does not depend on the order of bottles, so states are compared with bottles sorted
+pub(crate) fn canonical_key(level: &WaterSorting) -> Vec<u8> {
+    let mut bottles = level.bottles
+        .iter()
+        .map(|b| {
+            let [bottom, l1, l2, top] = b.layers().map(WaterSorting::map_color_to_u8);
+            [b.capacity as u8, WaterSorting::map_color_to_u8(b.color_lock), b.frozen_mask(), bottom, l1, l2, top]
+        })
+        .collect::<Vec<_>>();
+    bottles.sort();
+    let mut key = bottles.concat();
+    if let Some(countdown) = level.thaw_countdown() {
+        key.extend((countdown as u32).to_le_bytes());
+    }
+    key
+}
+
+/// Breadth-first walk over every state reachable from `level`, stopping at the first win or after
+/// `max_nodes` states. All legal pours are tried, including the ones the solver prunes by default.
+pub fn explore(level: &WaterSorting, max_nodes: usize, emit_certificate: bool) -> ExhaustiveReport {
+    let mut parents: Vec<(usize, Pour)> = vec![(0, Pour::new(0, 0))];
+    let mut visited = HashSet::new();
+    visited.insert(canonical_key(level));
+    let mut queue = VecDeque::new();
+    queue.push_back((level.clone(), 0usize));
+    let pruning = PruningRules { deduplicate_states: true, ..PruningRules::none() };
+
+    while let Some((state, node)) = queue.pop_front() {
+        if state.win() {
+            let mut solution = Vec::new();
+            let mut current = node;
+            while current != 0 {
+                solution.push(parents[current].1);
+                current = parents[current].0;
+            }
+            solution.reverse();
+            return ExhaustiveReport { states_explored: visited.len(), closed: false, solution: Some(solution), certificate: None };
+        }
+        for pour in state.possible_moves(&pruning) {
+            let mut child = state.clone();
+            child.pour(pour.from, pour.to);
+            child.old_state = None;
+            if visited.len() >= max_nodes {
+                return ExhaustiveReport { states_explored: visited.len(), closed: false, solution: None, certificate: None };
+            }
+            if visited.insert(canonical_key(&child)) {
+                parents.push((node, pour));
+                queue.push_back((child, parents.len() - 1));
+            }
+        }
+    }
+
+    let certificate = if emit_certificate {
+        let mut hashes = visited.iter().map(|key| fnv1a(key)).collect::<Vec<_>>();
+        hashes.sort_unstable();
+        let bytes = hashes.iter().flat_map(|h| h.to_le_bytes()).collect::<Vec<_>>();
+        Some(Certificate { states: visited.len(), digest: fnv1a(&bytes) })
+    } else {
+        None
+    };
+    ExhaustiveReport { states_explored: visited.len(), closed: true, solution: None, certificate }
+}
+
+#[wasm_bindgen]
+impl WaterSolver {
+    /// Explores the whole reachable state space (bounded by the configured `max_nodes`).
+    pub fn exhaustive(&self, emit_certificate: bool) -> ExhaustiveReport {
+        explore(&self.level, self.config.max_nodes, emit_certificate)
+    }
+
+    /// Re-runs the exhaustive search and checks that it closes with exactly the certified states.
+    pub fn verify_certificate(&self, certificate: &Certificate) -> bool {
+        let report = self.exhaustive(true);
+        report.is_unsolvable() && report.certificate.as_ref() == Some(certificate)
+    }
+}
+
+#[cfg(test)]
+mod proof_tests {
+    use crate::{Color, WaterSolver, WaterSorting};
+    use crate::proof::Certificate;
+    use crate::strategy::SolverConfig;
+
+    fn unsolvable() -> WaterSorting {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Blue, Color::Green, Color::Red, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Blue, Color::Red);
+        w.init_bottle_with_four_colors(Color::Green, Color::Red, Color::Blue, Color::Red);
+        w.init_empty_bottle();
+        w
+    }
+
+    #[test]
+    fn level_without_any_move_is_proven_unsolvable() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Blue, Color::Red, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Red, Color::Blue, Color::Red);
+
+        let report = WaterSolver::new(&w).exhaustive(false);
+
+        assert!(report.is_unsolvable());
+        assert_eq!(report.states_explored, 1);
+        assert!(report.certificate().is_none());
+    }
+
+    #[test]
+    fn unsolvable_level_is_closed_with_certificate() {
+        let w = unsolvable();
+        let solver = WaterSolver::new(&w);
+
+        let report = solver.exhaustive(true);
+
+        assert!(report.is_unsolvable());
+        assert!(report.states_explored > 1);
+        let certificate = report.certificate().unwrap();
+        assert_eq!(certificate.states, report.states_explored);
+        assert!(solver.verify_certificate(&certificate));
+    }
+
+    #[test]
+    fn tampered_certificate_is_rejected() {
+        let solver = WaterSolver::new(&unsolvable());
+        let certificate = solver.exhaustive(true).certificate().unwrap();
+
+        let tampered = Certificate { digest: certificate.digest ^ 1, ..certificate };
+
+        assert!(!solver.verify_certificate(&tampered));
+    }
+
+    #[test]
+    fn certificate_survives_text_round_trip() {
+        let certificate = WaterSolver::new(&unsolvable()).exhaustive(true).certificate().unwrap();
+
+        assert_eq!(Certificate::decode(&certificate.encode()), Some(certificate));
+        assert_eq!(Certificate::decode("nonsense"), None);
+    }
+
+    #[test]
+    fn solvable_level_returns_a_solution() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Green, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Green);
+        w.init_empty_bottle();
+
+        let report = WaterSolver::new(&w).exhaustive(true);
+
+        assert!(report.is_solvable());
+        assert!(!report.is_unsolvable());
+        let mut replay = w.clone();
+        report.solution().unwrap().iter().for_each(|p| replay.pour(p.from, p.to));
+        assert!(replay.win());
+    }
+
+    #[test]
+    fn node_limit_makes_the_result_inconclusive() {
+        let config = SolverConfig { max_nodes: 2, ..SolverConfig::new() };
+
+        let report = WaterSolver::with_config(&unsolvable(), config).exhaustive(true);
+
+        assert!(!report.closed);
+        assert!(!report.is_solvable());
+        assert!(!report.is_unsolvable());
+    }
+}
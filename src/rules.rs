@@ -0,0 +1,477 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+/// Presets of `RuleSet` for the two games.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum RuleMode {
+    // the whole top run, as far as it fits
+    #[default]
+    Water,
+    // exactly one unit, like the balls of "ball sort"
+    BallSort,
+    // the whole top run, and only when all of it fits
+    Strict,
+}
+
+/// Why `WaterSorting::try_pour` refused a pour.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PourRejection {
+    NoSuchBottle,
+    SameBottle,
+    EmptySource,
+    // the top layer waits for the thaw trigger
+    Frozen,
+    // the move limit has been reached
+    OutOfMoves,
+    // the countdown of a timed game has run out
+    TimeUp,
+    DestinationFull,
+    ColorMismatch,
+    // the destination only takes the color it is locked to
+    ColorLocked,
+    // the rules do not allow splitting the run and it does not fit
+    RunDoesNotFit,
+    // the rules keep completed bottles closed
+    BottleLocked,
+}
+
+impl Display for PourRejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PourRejection::NoSuchBottle => write!(f, "there is no such bottle"),
+            PourRejection::SameBottle => write!(f, "cannot pour a bottle into itself"),
+            PourRejection::EmptySource => write!(f, "there is nothing to pour"),
+            PourRejection::Frozen => write!(f, "the top layer is frozen"),
+            PourRejection::OutOfMoves => write!(f, "there are no moves left"),
+            PourRejection::TimeUp => write!(f, "the time is up"),
+            PourRejection::DestinationFull => write!(f, "the destination is full"),
+            PourRejection::ColorMismatch => write!(f, "the top colors do not match"),
+            PourRejection::ColorLocked => write!(f, "the destination only takes the color it is locked to"),
+            PourRejection::RunDoesNotFit => write!(f, "the whole run does not fit into the destination"),
+            PourRejection::BottleLocked => write!(f, "the bottle is completed and capped"),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum WinCondition {
+    // every bottle is empty or full of one color
+    #[default]
+    FullBottles,
+    // every bottle is empty or holds one color, however full
+    SingleColor,
+}
+
+/// Rules of a level, fixed when the level is created.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RuleSet {
+    // most units a pour moves, `None` moves the whole top run
+    pub pour_limit: Option<usize>,
+    // a pour may move only the part of a run that fits into the destination
+    pub split_runs: bool,
+    pub win_condition: WinCondition,
+    // completed bottles get capped: nothing can be poured out of them (or into them, they are full)
+    pub lock_completed: bool,
+    // the game is lost when the pours run out before the win, undone pours are given back
+    pub max_moves: Option<usize>,
+    pub max_undos: Option<usize>,
+    // empty bottles the player may add during the game
+    pub extra_bottles: usize,
+}
+
+#[wasm_bindgen]
+impl RuleSet {
+    pub fn water() -> Self {
+        RuleSet { pour_limit: None, split_runs: true, win_condition: WinCondition::FullBottles, lock_completed: false, max_moves: None, max_undos: None, extra_bottles: 0 }
+    }
+
+    pub fn ball_sort() -> Self {
+        RuleSet { pour_limit: Some(1), ..Self::water() }
+    }
+
+    pub fn strict() -> Self {
+        RuleSet { split_runs: false, ..Self::water() }
+    }
+
+    pub fn for_mode(mode: RuleMode) -> Self {
+        match mode {
+            RuleMode::Water => Self::water(),
+            RuleMode::BallSort => Self::ball_sort(),
+            RuleMode::Strict => Self::strict(),
+        }
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::water()
+    }
+}
+
+// `pour=all split=yes win=full lock=no`, the form used by level packs, limits are only written when set
+impl Display for RuleSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.pour_limit {
+            None => write!(f, "pour=all")?,
+            Some(limit) => write!(f, "pour={}", limit)?,
+        }
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        let win = match self.win_condition {
+            WinCondition::FullBottles => "full",
+            WinCondition::SingleColor => "single-color",
+        };
+        write!(f, " split={} win={} lock={}", yes_no(self.split_runs), win, yes_no(self.lock_completed))?;
+        if let Some(moves) = self.max_moves {
+            write!(f, " moves={}", moves)?;
+        }
+        if let Some(undos) = self.max_undos {
+            write!(f, " undos={}", undos)?;
+        }
+        if self.extra_bottles > 0 {
+            write!(f, " extra={}", self.extra_bottles)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = String;
+
+    // missing settings keep their water defaults, `water`, `ball-sort` and `strict` name the presets
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = RuleSet::water();
+        let flag = |value: &str| match value {
+            "yes" => Ok(true),
+            "no" => Ok(false),
+            _ => Err(format!("expected yes or no, got `{}`", value)),
+        };
+        for setting in s.split_whitespace() {
+            match setting.split_once('=') {
+                None if setting == "water" => rules = RuleSet::water(),
+                None if setting == "ball-sort" => rules = RuleSet::ball_sort(),
+                None if setting == "strict" => rules = RuleSet::strict(),
+                Some(("pour", "all")) => rules.pour_limit = None,
+                Some(("pour", limit)) => match limit.parse() {
+                    Ok(0) | Err(_) => return Err(format!("pour expects all or a positive number, got `{}`", limit)),
+                    Ok(limit) => rules.pour_limit = Some(limit),
+                },
+                Some(("split", value)) => rules.split_runs = flag(value)?,
+                Some(("win", "full")) => rules.win_condition = WinCondition::FullBottles,
+                Some(("win", "single-color")) => rules.win_condition = WinCondition::SingleColor,
+                Some(("lock" | "cap", value)) => rules.lock_completed = flag(value)?,
+                Some(("moves", moves)) => match moves.parse() {
+                    Ok(0) | Err(_) => return Err(format!("moves expects a positive number, got `{}`", moves)),
+                    Ok(moves) => rules.max_moves = Some(moves),
+                },
+                Some(("undos", undos)) => rules.max_undos = Some(undos.parse().map_err(|_| format!("undos expects a number, got `{}`", undos))?),
+                Some(("extra", extra)) => rules.extra_bottles = extra.parse().map_err(|_| format!("extra expects a number, got `{}`", extra))?,
+                _ => return Err(format!("unknown rule `{}`", setting)),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod rules_tests {
+    use crate::{Color, Pour, WaterSolver, WaterSorting, CAPPED_MARKER};
+    use crate::rules::{PourRejection, RuleMode, RuleSet, WinCondition};
+    use crate::strategy::{Algorithm, PruningRules, SolverConfig};
+
+    fn level(rule_mode: RuleMode) -> WaterSorting {
+        let mut w = WaterSorting::with_rule_mode(rule_mode);
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Blue, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Red, Color::Red);
+        w.init_empty_bottle();
+        w
+    }
+
+    #[test]
+    fn ball_sort_pour_moves_a_single_unit() {
+        let mut w = level(RuleMode::BallSort);
+
+        w.pour(0, 2);
+
+        assert_eq!(w.bottles[0].available_empty_space(), 1);
+        assert_eq!(w.bottles[2].available_empty_space(), 3);
+        assert_eq!(w.rules(), RuleSet::ball_sort());
+    }
+
+    #[test]
+    fn water_pour_moves_the_whole_run() {
+        let mut w = level(RuleMode::Water);
+
+        w.pour(0, 2);
+
+        assert_eq!(w.bottles[0].available_empty_space(), 2);
+        assert_eq!(w.bottles[2].available_empty_space(), 2);
+    }
+
+    #[test]
+    fn ball_sort_lists_moves_that_would_split_a_run() {
+        let mut water = level(RuleMode::Water);
+        water.init_bottle_with_three_colors(Color::Green, Color::Green, Color::Blue);
+        let mut balls = level(RuleMode::BallSort);
+        balls.init_bottle_with_three_colors(Color::Green, Color::Green, Color::Blue);
+
+        // the run of two blues fits into the last bottle only one unit at a time
+        assert!(!water.possible_moves(&PruningRules::new()).contains(&Pour::new(0, 3)));
+        assert!(balls.possible_moves(&PruningRules::new()).contains(&Pour::new(0, 3)));
+    }
+
+    #[test]
+    fn ball_sort_is_won_with_full_single_color_bottles() {
+        let mut w = level(RuleMode::BallSort);
+
+        for (from, to) in [(0, 2), (0, 2), (1, 0), (1, 0), (2, 1), (2, 1)] {
+            assert!(!w.win());
+            w.pour(from, to);
+        }
+
+        assert!(w.win());
+    }
+
+    #[test]
+    fn solver_plays_by_the_ball_sort_rules() {
+        let config = SolverConfig { require_optimal: true, ..SolverConfig::with_algorithm(Algorithm::Bfs) };
+
+        let water = WaterSolver::with_config(&level(RuleMode::Water), config).solve_configured().0;
+        let balls = WaterSolver::with_config(&level(RuleMode::BallSort), config).solve_configured().0;
+
+        assert_eq!(water.len(), 3);
+        assert_eq!(balls.len(), 6);
+        let mut w = level(RuleMode::BallSort);
+        balls.iter().for_each(|p| w.pour(p.from, p.to));
+        assert!(w.win());
+    }
+
+    #[test]
+    fn pour_limit_moves_at_most_that_many_units() {
+        let mut w = WaterSorting::with_rules(RuleSet { pour_limit: Some(2), ..RuleSet::water() });
+        w.init_bottle_with_four_colors(Color::Blue, Color::Red, Color::Red, Color::Red);
+        w.init_empty_bottle();
+
+        w.pour(0, 1);
+
+        assert_eq!(w.bottles[1].available_empty_space(), 2);
+        assert_eq!(w.bottles[0].top_color(), Some(Color::Red));
+    }
+
+    #[test]
+    fn runs_are_not_split_when_splitting_is_off() {
+        let rules = RuleSet { split_runs: false, ..RuleSet::water() };
+        let mut w = WaterSorting::with_rules(rules);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Red, Color::Red, Color::Red);
+        w.init_bottle_with_three_colors(Color::Green, Color::Green, Color::Red);
+        w.init_empty_bottle();
+
+        w.pour(0, 1);
+
+        assert_eq!(w.bottles[1].available_empty_space(), 1);
+        assert_eq!(w.moves_made(), 0);
+        assert!(!w.possible_moves(&PruningRules::none()).contains(&Pour::new(0, 1)));
+        w.pour(0, 2);
+        assert_eq!(w.bottles[2].available_empty_space(), 1);
+    }
+
+    #[test]
+    fn strict_pour_rejects_a_run_that_does_not_fit() {
+        let mut w = WaterSorting::with_rule_mode(RuleMode::Strict);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Red, Color::Red, Color::Red);
+        w.init_bottle_with_three_colors(Color::Green, Color::Green, Color::Red);
+        w.init_empty_bottle();
+        let before = w.clone();
+
+        assert_eq!(w.check_pour(0, 1), Some(PourRejection::RunDoesNotFit));
+        assert_eq!(w.try_pour(0, 1), Err(PourRejection::RunDoesNotFit));
+        assert_eq!(w.bottles, before.bottles);
+        assert_eq!(w.moves_made(), 0);
+        assert_eq!(w.try_pour(0, 2), Ok(3));
+        assert_eq!(w.moves_made(), 1);
+    }
+
+    #[test]
+    fn rejected_pours_tell_why() {
+        let mut w = WaterSorting::with_rules(RuleSet { lock_completed: true, ..RuleSet::strict() });
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Red);
+        w.init_bottle_with_two_colors(Color::Blue, Color::Green);
+        w.init_empty_bottle();
+
+        assert_eq!(w.try_pour(0, 3), Err(PourRejection::NoSuchBottle));
+        assert_eq!(w.try_pour(1, 1), Err(PourRejection::SameBottle));
+        assert_eq!(w.try_pour(2, 1), Err(PourRejection::EmptySource));
+        assert_eq!(w.try_pour(1, 0), Err(PourRejection::DestinationFull));
+        assert_eq!(w.try_pour(0, 2), Err(PourRejection::BottleLocked));
+        w.rules = RuleSet::strict();
+        assert_eq!(w.try_pour(0, 1), Err(PourRejection::ColorMismatch));
+        assert_eq!(PourRejection::RunDoesNotFit.to_string(), "the whole run does not fit into the destination");
+    }
+
+    #[test]
+    fn rejected_pour_keeps_the_pour_to_undo() {
+        let mut w = WaterSorting::with_rules(RuleSet { max_undos: Some(1), ..RuleSet::water() });
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Blue, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Red, Color::Red);
+        w.init_empty_bottle();
+
+        w.pour(0, 2);
+        assert_eq!(w.try_pour(0, 0), Err(PourRejection::SameBottle));
+        w.undo();
+
+        assert_eq!(w.bottles[2].available_empty_space(), 4);
+        assert_eq!(w.moves_made(), 0);
+        assert_eq!(w.undos_made(), 1);
+    }
+
+    #[test]
+    fn strict_solutions_replay_without_rejections() {
+        let mut w = level(RuleMode::Strict);
+
+        let solution = WaterSolver::new(&w).solve_configured().0;
+
+        assert!(!solution.is_empty());
+        solution.iter().for_each(|p| assert!(w.try_pour(p.from, p.to).is_ok()));
+        assert!(w.win());
+    }
+
+    #[test]
+    fn completed_bottles_are_locked() {
+        let mut w = WaterSorting::with_rules(RuleSet { lock_completed: true, ..RuleSet::water() });
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Red);
+        w.init_empty_bottle();
+
+        w.pour(0, 1);
+
+        assert!(w.bottles[1].is_empty());
+        assert!(w.possible_moves(&PruningRules::none()).is_empty());
+    }
+
+    #[test]
+    fn completed_bottles_are_capped_and_marked() {
+        let mut w = WaterSorting::with_rules("cap=yes".parse().unwrap());
+        w.init_bottle_with_four_colors(Color::Blue, Color::Red, Color::Red, Color::Red);
+        w.init_bottle_with_one_color(Color::Red);
+        w.init_empty_bottle();
+
+        w.pour(0, 1);
+
+        assert!(w.is_capped(1));
+        assert_eq!(w.check_pour(1, 2), Some(PourRejection::BottleLocked));
+        assert_eq!(w.check_pour(2, 1), Some(PourRejection::EmptySource));
+        w.bottles();
+        assert!(w.bottles_serialized[4..8].iter().all(|&c| c & CAPPED_MARKER != 0));
+        assert!(w.bottles_serialized[..4].iter().all(|&c| c & CAPPED_MARKER == 0));
+        assert!(w.to_string().contains("2: 🟥🟥🟥🟥 (capped)"));
+    }
+
+    #[test]
+    fn capped_bottles_make_no_move_available() {
+        let mut w = WaterSorting::with_rules("cap=yes".parse().unwrap());
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Red);
+        w.init_bottle_with_one_color(Color::Red);
+
+        assert!(!w.move_available());
+        assert!(w.under_rules(RuleSet::water()).move_available());
+        w.init_empty_bottle();
+        assert!(w.move_available());
+        w.bottles[1].pop();
+        assert!(!w.move_available());
+    }
+
+    #[test]
+    fn solver_skips_capped_bottles() {
+        let mut open = WaterSorting::new();
+        open.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Blue, Color::Blue);
+        open.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Green, Color::Green);
+        open.init_bottle_with_four_colors(Color::Green, Color::Green, Color::Red, Color::Red);
+        open.init_bottle_with_four_colors(Color::Yellow, Color::Yellow, Color::Yellow, Color::Yellow);
+        open.init_empty_bottle();
+        open.init_empty_bottle();
+        let capped = open.under_rules(RuleSet { lock_completed: true, ..RuleSet::water() });
+        let config = SolverConfig { pruning: PruningRules::none(), ..SolverConfig::with_algorithm(Algorithm::Bfs) };
+
+        let (open_solution, open_stats) = WaterSolver::with_config(&open, config).solve_configured();
+        let (capped_solution, capped_stats) = WaterSolver::with_config(&capped, config).solve_configured();
+
+        assert_eq!(capped_solution.len(), open_solution.len());
+        assert!(capped_stats.nodes_expanded < open_stats.nodes_expanded);
+    }
+
+    #[test]
+    fn running_out_of_moves_loses_the_game() {
+        let mut w = level(RuleMode::Water).under_rules("moves=2".parse().unwrap());
+
+        w.pour(0, 2);
+        assert_eq!(w.moves_left(), Some(1));
+        w.pour(1, 0);
+
+        assert!(w.lost());
+        assert_eq!(w.try_pour(2, 1), Err(PourRejection::OutOfMoves));
+        assert!(!w.undo_available());
+        w.undo();
+        assert_eq!(w.moves_made(), 2);
+    }
+
+    #[test]
+    fn win_on_the_last_move_is_not_lost() {
+        let mut w = level(RuleMode::Water).under_rules("moves=3".parse().unwrap());
+
+        for (from, to) in [(0, 2), (1, 0), (2, 1)] {
+            w.pour(from, to);
+        }
+
+        assert!(w.win());
+        assert!(!w.lost());
+        assert_eq!(w.moves_left(), Some(0));
+    }
+
+    #[test]
+    fn undos_stop_at_the_limit() {
+        let mut w = level(RuleMode::Water).under_rules("undos=1".parse().unwrap());
+
+        w.pour(0, 2);
+        w.undo();
+        w.pour(0, 2);
+        assert_eq!(w.undos_left(), Some(0));
+        assert!(!w.undo_available());
+        w.undo();
+
+        assert_eq!(w.moves_made(), 1);
+        assert_eq!(w.moves_left(), None);
+    }
+
+    #[test]
+    fn single_color_win_accepts_bottles_that_are_not_full() {
+        let mut w = WaterSorting::with_rules(RuleSet { win_condition: WinCondition::SingleColor, ..RuleSet::water() });
+        w.init_bottle_with_two_colors(Color::Red, Color::Blue);
+        w.init_bottle_with_one_color(Color::Red);
+        w.init_empty_bottle();
+        let mut full = w.clone();
+        full.rules = RuleSet::water();
+
+        let solution = WaterSolver::with_config(&w, SolverConfig { require_optimal: true, ..SolverConfig::new() }).solve_configured().0;
+
+        assert_eq!(solution.len(), 1);
+        w.pour(solution[0].from, solution[0].to);
+        assert!(w.win());
+        full.pour(solution[0].from, solution[0].to);
+        assert!(!full.win());
+    }
+
+    #[test]
+    fn rules_survive_a_text_round_trip() {
+        let rules = RuleSet { pour_limit: Some(3), split_runs: false, win_condition: WinCondition::SingleColor, lock_completed: true, max_moves: Some(20), max_undos: Some(0), extra_bottles: 2 };
+
+        assert_eq!(rules.to_string(), "pour=3 split=no win=single-color lock=yes moves=20 undos=0 extra=2");
+        assert_eq!(rules.to_string().parse(), Ok(rules));
+        assert_eq!("ball-sort lock=yes".parse(), Ok(RuleSet { lock_completed: true, ..RuleSet::ball_sort() }));
+        assert_eq!("strict".parse(), Ok(RuleSet::strict()));
+        assert!("pour=0".parse::<RuleSet>().is_err());
+        assert!("gravity=off".parse::<RuleSet>().is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt::{Display, Formatter};
+use wasm_bindgen::prelude::*;
+use crate::{WaterSolver, WaterSorting};
+use crate::strategy::{Algorithm, SolverConfig};
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StarThresholds {
+    // share of par that may be exceeded and still earn three (or two) stars
+    pub three_stars_margin: f64,
+    pub two_stars_margin: f64,
+    // moves added to the count for every undo
+    pub undo_penalty: usize,
+}
+
+#[wasm_bindgen]
+impl StarThresholds {
+    pub fn new() -> Self {
+        StarThresholds { three_stars_margin: 0.0, two_stars_margin: 0.5, undo_penalty: 1 }
+    }
+}
+
+impl Default for StarThresholds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StarRating {
+    pub stars: u8,
+    pub par: usize,
+    pub moves: usize,
+    pub undos: usize,
+    // moves plus the undo penalty, this is what gets compared with par
+    pub counted_moves: usize,
+}
+
+impl Display for StarRating {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{} {} moves, {} undos (par {})", "★".repeat(self.stars as usize), "☆".repeat(3 - self.stars as usize), self.moves, self.undos, self.par)
+    }
+}
+
+// states par may expand, so finding it never stalls the game on a hard level
+pub const PAR_MAX_NODES: usize = 100_000;
+
+fn allowed_moves(par: usize, margin: f64) -> usize {
+    par + (par as f64 * margin.max(0.0)).ceil() as usize
+}
+
+/// Stars for a won game: three within the three stars margin over par, two within the two stars
+/// margin and one otherwise. `None` while the game is not won.
+pub fn star_rating(game: &WaterSorting, par: usize, thresholds: &StarThresholds) -> Option<StarRating> {
+    if !game.win() {
+        return None;
+    }
+    let (moves, undos) = (game.moves_made(), game.undos_made());
+    let counted_moves = moves + undos * thresholds.undo_penalty;
+    let stars = if counted_moves <= allowed_moves(par, thresholds.three_stars_margin) {
+        3
+    } else if counted_moves <= allowed_moves(par, thresholds.two_stars_margin) {
+        2
+    } else {
+        1
+    };
+    Some(StarRating { stars, par, moves, undos, counted_moves })
+}
+
+#[wasm_bindgen]
+impl WaterSorting {
+    /// Star rating of the game once `win()` is true, see `star_rating`.
+    pub fn stars(&self, par: usize, thresholds: &StarThresholds) -> Option<StarRating> {
+        star_rating(self, par, thresholds)
+    }
+}
+
+#[wasm_bindgen]
+impl WaterSolver {
+    /// Length of the shortest solution, searched with A* within `PAR_MAX_NODES` (or the configured
+    /// `max_nodes` when lower). `None` when the level has no solution or the budget runs out.
+    pub fn par(&self) -> Option<usize> {
+        if self.level.win() {
+            return Some(0);
+        }
+        let max_nodes = self.config.max_nodes.min(PAR_MAX_NODES);
+        let config = SolverConfig { algorithm: Algorithm::AStar, require_optimal: true, max_depth: usize::MAX, max_nodes, ..self.config };
+        let solution = self.solve_using(&config).0;
+        if solution.is_empty() { None } else { Some(solution.len()) }
+    }
+}
+
+#[cfg(test)]
+mod scoring_tests {
+    use crate::{Color, WaterSolver, WaterSorting};
+    use crate::scoring::{star_rating, StarThresholds};
+    use crate::strategy::SolverConfig;
+
+    // solvable in three pours: 0 -> 2, 1 -> 0, 2 -> 1
+    fn level() -> WaterSorting {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Blue, Color::Red);
+        w.init_empty_bottle();
+        w
+    }
+
+    fn solve(w: &mut WaterSorting) {
+        w.pour(0, 2);
+        w.pour(1, 0);
+        w.pour(2, 1);
+    }
+
+    #[test]
+    fn par_is_the_optimal_solution_length() {
+        assert_eq!(WaterSolver::new(&level()).par(), Some(3));
+    }
+
+    #[test]
+    fn par_is_unknown_when_the_node_budget_runs_out() {
+        let solver = WaterSolver::with_config(&level(), SolverConfig { max_nodes: 1, ..SolverConfig::new() });
+
+        assert_eq!(solver.par(), None);
+    }
+
+    #[test]
+    fn moves_and_undos_are_counted() {
+        let mut w = level();
+
+        w.pour(0, 2);
+        w.pour(1, 0);
+        w.undo();
+        w.undo();
+
+        assert_eq!(w.moves_made(), 1);
+        assert_eq!(w.undos_made(), 1);
+    }
+
+    #[test]
+    fn pours_that_change_nothing_are_not_counted() {
+        let mut w = level();
+
+        w.pour(0, 1);
+        w.undo();
+
+        assert_eq!(w.moves_made(), 0);
+        assert_eq!(w.undos_made(), 0);
+    }
+
+    #[test]
+    fn optimal_game_earns_three_stars() {
+        let mut w = level();
+        solve(&mut w);
+
+        let rating = w.stars(3, &StarThresholds::new()).unwrap();
+
+        assert_eq!(rating.stars, 3);
+        assert_eq!((rating.moves, rating.undos, rating.par), (3, 0, 3));
+    }
+
+    #[test]
+    fn undos_cost_stars() {
+        let mut w = level();
+        w.pour(0, 2);
+        w.undo();
+        w.pour(0, 2);
+        w.undo();
+        solve(&mut w);
+
+        let rating = star_rating(&w, 3, &StarThresholds::new()).unwrap();
+        let lenient = star_rating(&w, 3, &StarThresholds { undo_penalty: 0, ..StarThresholds::new() }).unwrap();
+
+        assert_eq!((rating.counted_moves, rating.stars), (5, 2));
+        assert_eq!(lenient.stars, 3);
+    }
+
+    #[test]
+    fn thresholds_are_configurable() {
+        let mut w = level();
+        w.pour(0, 2);
+        w.undo();
+        w.pour(0, 2);
+        w.undo();
+        solve(&mut w);
+        let generous = StarThresholds { three_stars_margin: 1.0, two_stars_margin: 2.0, undo_penalty: 1 };
+
+        assert_eq!(star_rating(&w, 2, &StarThresholds::new()).unwrap().stars, 1);
+        assert_eq!(star_rating(&w, 3, &StarThresholds::new()).unwrap().stars, 2);
+        assert_eq!(star_rating(&w, 3, &generous).unwrap().stars, 3);
+    }
+
+    #[test]
+    fn unfinished_game_is_not_rated() {
+        let mut w = level();
+        w.pour(0, 2);
+
+        assert_eq!(w.stars(3, &StarThresholds::new()), None);
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
+use wasm_bindgen::prelude::*;
+use crate::{Bottle, WaterSorting};
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    fn date_now() -> f64;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_ms() -> f64 {
+    date_now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_ms() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64() * 1000.0).unwrap_or(0.0)
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolverStats {
+    pub nodes_expanded: usize,
+    pub states_deduplicated: usize,
+    pub max_depth: usize,
+    pub time_ms: f64,
+    pub peak_memory_bytes: usize,
+    // a depth, node or beam width limit cut part of the search space off
+    pub search_truncated: bool,
+    // branching_histogram[n] = number of expanded nodes that had exactly n moves available
+    branching_histogram: Vec<usize>,
+}
+
+#[wasm_bindgen]
+impl SolverStats {
+    pub fn branching_histogram(&self) -> Vec<usize> {
+        self.branching_histogram.clone()
+    }
+
+    pub fn average_branching_factor(&self) -> f64 {
+        if self.nodes_expanded == 0 {
+            return 0.0;
+        }
+        let moves: usize = self.branching_histogram.iter().enumerate().map(|(n, count)| n * count).sum();
+        moves as f64 / self.nodes_expanded as f64
+    }
+}
+
+impl SolverStats {
+    pub(crate) fn record_expansion(&mut self, depth: usize, branching: usize) {
+        self.nodes_expanded += 1;
+        self.max_depth = self.max_depth.max(depth);
+        if self.branching_histogram.len() <= branching {
+            self.branching_histogram.resize(branching + 1, 0);
+        }
+        self.branching_histogram[branching] += 1;
+    }
+
+    pub(crate) fn record_duplicate(&mut self) {
+        self.states_deduplicated += 1;
+    }
+
+    // rough upper bound of memory held by `live_states` copies of `level` (bottles plus undo copy)
+    pub(crate) fn record_live_states(&mut self, level: &WaterSorting, live_states: usize) {
+        let per_state = size_of::<WaterSorting>() + 2 * level.bottles.len() * size_of::<Bottle>();
+        self.peak_memory_bytes = self.peak_memory_bytes.max(per_state * live_states);
+    }
+}
+
+impl Display for SolverStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Nodes expanded: {}", self.nodes_expanded)?;
+        writeln!(f, "States deduplicated: {}", self.states_deduplicated)?;
+        writeln!(f, "Max depth: {}", self.max_depth)?;
+        writeln!(f, "Average branching factor: {:.2}", self.average_branching_factor())?;
+        writeln!(f, "Branching histogram: {:?}", self.branching_histogram)?;
+        writeln!(f, "Time: {:.1} ms", self.time_ms)?;
+        writeln!(f, "Peak memory (estimate): {} B", self.peak_memory_bytes)?;
+        write!(f, "Search truncated: {}", self.search_truncated)
+    }
+}
@@ -0,0 +1,671 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+use crate::{Color, Pour, WaterSorting};
+use crate::rules::{RuleSet, WinCondition};
+use crate::stats::SolverStats;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Algorithm {
+    Dfs,
+    Bfs,
+    AStar,
+    Beam,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dfs" => Ok(Algorithm::Dfs),
+            "bfs" => Ok(Algorithm::Bfs),
+            "astar" | "a*" => Ok(Algorithm::AStar),
+            "beam" => Ok(Algorithm::Beam),
+            other => Err(format!("unknown algorithm: {}", other)),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Algorithm::Dfs => write!(f, "dfs"),
+            Algorithm::Bfs => write!(f, "bfs"),
+            Algorithm::AStar => write!(f, "astar"),
+            Algorithm::Beam => write!(f, "beam"),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MoveOrdering {
+    // in the order `possible_moves` lists them
+    Generated,
+    // pours moving the most liquid first
+    LargestPourFirst,
+    // pours completing a bottle first, pours into empty bottles last
+    CompletingFirst,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PruningRules {
+    // never expand a state that was already reached with the same or fewer pours
+    pub deduplicate_states: bool,
+    // never pour out of a solved bottle
+    pub skip_solved_bottles: bool,
+    // only pour when the whole top run fits into the destination
+    pub skip_partial_pours: bool,
+    // never move a bottle holding a single color into an empty one (it only relocates it)
+    pub skip_single_color_to_empty: bool,
+    // treat empty bottles of the same size and lock as one destination
+    pub skip_symmetric_empty_bottles: bool,
+}
+
+#[wasm_bindgen]
+impl PruningRules {
+    pub fn new() -> Self {
+        PruningRules {
+            deduplicate_states: true,
+            skip_solved_bottles: true,
+            skip_partial_pours: true,
+            skip_single_color_to_empty: true,
+            skip_symmetric_empty_bottles: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        PruningRules {
+            deduplicate_states: false,
+            skip_solved_bottles: false,
+            skip_partial_pours: false,
+            skip_single_color_to_empty: false,
+            skip_symmetric_empty_bottles: false,
+        }
+    }
+}
+
+impl Default for PruningRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SolverConfig {
+    pub algorithm: Algorithm,
+    // maximum number of pours in a solution
+    pub max_depth: usize,
+    // maximum number of expanded states
+    pub max_nodes: usize,
+    // number of states kept per layer by `Algorithm::Beam`
+    pub beam_width: usize,
+    pub pruning: PruningRules,
+    pub move_ordering: MoveOrdering,
+    // only accept a shortest solution, switching to an optimal strategy and dropping
+    // the pruning rules that can hide one if needed
+    pub require_optimal: bool,
+}
+
+#[wasm_bindgen]
+impl SolverConfig {
+    pub fn new() -> Self {
+        SolverConfig {
+            algorithm: Algorithm::Dfs,
+            max_depth: 20,
+            max_nodes: usize::MAX,
+            beam_width: 100,
+            pruning: PruningRules::new(),
+            move_ordering: MoveOrdering::Generated,
+            require_optimal: false,
+        }
+    }
+
+    pub fn with_algorithm(algorithm: Algorithm) -> Self {
+        SolverConfig { algorithm, ..Self::new() }
+    }
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SolverConfig {
+    pub fn strategy(&self) -> Box<dyn SolverStrategy> {
+        let strategy: Box<dyn SolverStrategy> = match self.algorithm {
+            Algorithm::Dfs if self.require_optimal => Box::new(IterativeDeepening),
+            Algorithm::Dfs => Box::new(DepthFirst),
+            Algorithm::Bfs => Box::new(BreadthFirst),
+            Algorithm::AStar => Box::new(AStar),
+            Algorithm::Beam => Box::new(Beam),
+        };
+        if self.require_optimal && !strategy.is_optimal() {
+            return Box::new(AStar);
+        }
+        strategy
+    }
+
+    /// Pruning the search applies: an optimal search keeps only the rules that never drop a
+    /// shortest solution, partial pours and moves of a single color into an empty bottle can be part of one.
+    pub fn effective_pruning(&self) -> PruningRules {
+        if !self.require_optimal {
+            return self.pruning;
+        }
+        PruningRules { skip_partial_pours: false, skip_single_color_to_empty: false, ..self.pruning }
+    }
+
+    // `remaining_pours_estimate` is only proven to be a lower bound for whole runs of plain colors
+    // under the water rules, an optimal search does without it otherwise
+    fn estimate(&self, level: &WaterSorting) -> usize {
+        let rules = level.rules();
+        let water = RuleSet::water();
+        let plain = rules.pour_limit == water.pour_limit && rules.split_runs == water.split_runs &&
+            rules.win_condition == water.win_condition && !level.bottles.iter().any(|b| b.layers().contains(&Some(Color::Rainbow)));
+        if self.require_optimal && !plain { 0 } else { remaining_pours_estimate(level) }
+    }
+}
+
+pub trait SolverStrategy {
+    fn name(&self) -> &'static str;
+
+    // whether a returned solution is guaranteed to be a shortest one
+    fn is_optimal(&self) -> bool;
+
+    fn solve(&self, level: &WaterSorting, config: &SolverConfig, stats: &mut SolverStats) -> Option<Vec<Pour>>;
+}
+
+/// Lower bound of pours still needed: every pour joins at most one run of color with another.
+pub fn remaining_pours_estimate(level: &WaterSorting) -> usize {
+    if level.win() {
+        return 0;
+    }
+    let (runs, colors) = color_runs(level);
+    match level.rules().win_condition {
+        WinCondition::FullBottles => runs.saturating_sub(colors),
+        // a bottle is done as soon as it holds a single run
+        WinCondition::SingleColor => runs.saturating_sub(level.bottles.iter().filter(|b| !b.is_empty()).count()),
+    }
+}
+
+// number of runs of the same color in all bottles and number of distinct colors,
+// rainbow layers belong to the runs around them
+pub(crate) fn color_runs(level: &WaterSorting) -> (usize, usize) {
+    let mut colors = HashSet::new();
+    let mut runs = 0;
+    for bottle in &level.bottles {
+        let mut previous = None;
+        for layer in bottle.layers().into_iter().flatten().filter(|&layer| layer != Color::Rainbow) {
+            colors.insert(layer);
+            if previous != Some(layer) {
+                runs += 1;
+            }
+            previous = Some(layer);
+        }
+    }
+    (runs, colors.len())
+}
+
+fn successors(state: &WaterSorting, config: &SolverConfig) -> Vec<(Pour, WaterSorting)> {
+    let mut children = state
+        .possible_moves(&config.effective_pruning())
+        .into_iter()
+        .filter_map(|pour| {
+            let mut child = state.clone();
+            child.pour(pour.from, pour.to);
+            child.old_state = None;
+            if child == *state { None } else { Some((pour, child)) }
+        })
+        .collect::<Vec<_>>();
+    match config.move_ordering {
+        MoveOrdering::Generated => {}
+        MoveOrdering::LargestPourFirst => children.sort_by_key(|(pour, child)| {
+            Reverse(child.bottles[pour.to].available_empty_space().abs_diff(state.bottles[pour.to].available_empty_space()))
+        }),
+        MoveOrdering::CompletingFirst => children.sort_by_key(|(pour, child)| {
+            if child.bottles[pour.to].is_solved() { 0 } else if state.bottles[pour.to].is_empty() { 2 } else { 1 }
+        }),
+    }
+    children
+}
+
+fn path_to(parents: &[(usize, Pour)], mut node: usize) -> Vec<Pour> {
+    let mut path = Vec::new();
+    while node != 0 {
+        let (parent, pour) = parents[node];
+        path.push(pour);
+        node = parent;
+    }
+    path.reverse();
+    path
+}
+
+pub struct DepthFirst;
+
+pub struct IterativeDeepening;
+
+pub struct BreadthFirst;
+
+pub struct AStar;
+
+pub struct Beam;
+
+struct DepthFirstSearch<'a> {
+    config: &'a SolverConfig,
+    stats: &'a mut SolverStats,
+    best_depth: HashMap<WaterSorting, usize>,
+    path: Vec<Pour>,
+    depth_cut: bool,
+}
+
+impl DepthFirstSearch<'_> {
+    fn run(&mut self, state: &WaterSorting, limit: usize) -> bool {
+        if state.win() {
+            return true;
+        }
+        if self.path.len() >= limit {
+            self.depth_cut = true;
+            return false;
+        }
+        if self.stats.nodes_expanded >= self.config.max_nodes {
+            self.stats.search_truncated = true;
+            return false;
+        }
+        let children = successors(state, self.config);
+        self.stats.record_expansion(self.path.len(), children.len());
+        self.stats.record_live_states(state, self.best_depth.len() + self.path.len());
+        for (pour, child) in children {
+            let depth = self.path.len() + 1;
+            if self.config.pruning.deduplicate_states {
+                match self.best_depth.get(&child) {
+                    Some(&seen) if seen <= depth => {
+                        self.stats.record_duplicate();
+                        continue;
+                    }
+                    _ => { self.best_depth.insert(child.clone(), depth); }
+                }
+            }
+            self.path.push(pour);
+            if self.run(&child, limit) {
+                return true;
+            }
+            self.path.pop();
+        }
+        false
+    }
+}
+
+impl SolverStrategy for DepthFirst {
+    fn name(&self) -> &'static str {
+        "dfs"
+    }
+
+    fn is_optimal(&self) -> bool {
+        false
+    }
+
+    fn solve(&self, level: &WaterSorting, config: &SolverConfig, stats: &mut SolverStats) -> Option<Vec<Pour>> {
+        let mut search = DepthFirstSearch { config, stats, best_depth: HashMap::new(), path: Vec::new(), depth_cut: false };
+        search.best_depth.insert(level.clone(), 0);
+        if search.run(level, config.max_depth) {
+            return Some(search.path);
+        }
+        // branches cut by the depth limit only matter when no solution was found
+        search.stats.search_truncated |= search.depth_cut;
+        None
+    }
+}
+
+impl SolverStrategy for IterativeDeepening {
+    fn name(&self) -> &'static str {
+        "iterative deepening dfs"
+    }
+
+    fn is_optimal(&self) -> bool {
+        true
+    }
+
+    fn solve(&self, level: &WaterSorting, config: &SolverConfig, stats: &mut SolverStats) -> Option<Vec<Pour>> {
+        for limit in 0..=config.max_depth {
+            let mut search = DepthFirstSearch { config, stats: &mut *stats, best_depth: HashMap::new(), path: Vec::new(), depth_cut: false };
+            search.best_depth.insert(level.clone(), 0);
+            if search.run(level, limit) {
+                return Some(search.path);
+            }
+            // nothing was cut by the limit, so a deeper search cannot find more
+            if !search.depth_cut || search.stats.search_truncated {
+                return None;
+            }
+        }
+        stats.search_truncated = true;
+        None
+    }
+}
+
+impl SolverStrategy for BreadthFirst {
+    fn name(&self) -> &'static str {
+        "bfs"
+    }
+
+    fn is_optimal(&self) -> bool {
+        true
+    }
+
+    fn solve(&self, level: &WaterSorting, config: &SolverConfig, stats: &mut SolverStats) -> Option<Vec<Pour>> {
+        if level.win() {
+            return Some(Vec::new());
+        }
+        let mut parents = vec![(0, Pour::new(0, 0))];
+        let mut visited = HashSet::new();
+        visited.insert(level.clone());
+        let mut queue = VecDeque::new();
+        queue.push_back((level.clone(), 0usize, 0usize));
+
+        while let Some((state, node, depth)) = queue.pop_front() {
+            if depth >= config.max_depth {
+                stats.search_truncated = true;
+                continue;
+            }
+            if stats.nodes_expanded >= config.max_nodes {
+                stats.search_truncated = true;
+                break;
+            }
+            let children = successors(&state, config);
+            stats.record_expansion(depth, children.len());
+            stats.record_live_states(&state, visited.len() + queue.len());
+            for (pour, child) in children {
+                if config.pruning.deduplicate_states && !visited.insert(child.clone()) {
+                    stats.record_duplicate();
+                    continue;
+                }
+                parents.push((node, pour));
+                if child.win() {
+                    return Some(path_to(&parents, parents.len() - 1));
+                }
+                queue.push_back((child, parents.len() - 1, depth + 1));
+            }
+        }
+        None
+    }
+}
+
+impl SolverStrategy for AStar {
+    fn name(&self) -> &'static str {
+        "astar"
+    }
+
+    fn is_optimal(&self) -> bool {
+        true
+    }
+
+    fn solve(&self, level: &WaterSorting, config: &SolverConfig, stats: &mut SolverStats) -> Option<Vec<Pour>> {
+        let mut parents = vec![(0, Pour::new(0, 0))];
+        let mut states = vec![level.clone()];
+        let mut best_cost = HashMap::new();
+        best_cost.insert(level.clone(), 0usize);
+        // ordered by lowest estimated total, then by the deepest node
+        let mut open = BinaryHeap::new();
+        open.push((Reverse(config.estimate(level)), 0usize, Reverse(0usize)));
+
+        while let Some((_, cost, Reverse(node))) = open.pop() {
+            let state = states[node].clone();
+            if best_cost.get(&state).is_some_and(|&best| best < cost) {
+                continue;
+            }
+            if state.win() {
+                return Some(path_to(&parents, node));
+            }
+            if cost >= config.max_depth {
+                stats.search_truncated = true;
+                continue;
+            }
+            if stats.nodes_expanded >= config.max_nodes {
+                stats.search_truncated = true;
+                break;
+            }
+            let children = successors(&state, config);
+            stats.record_expansion(cost, children.len());
+            stats.record_live_states(&state, best_cost.len() + open.len());
+            for (pour, child) in children {
+                let child_cost = cost + 1;
+                if config.pruning.deduplicate_states {
+                    if best_cost.get(&child).is_some_and(|&best| best <= child_cost) {
+                        stats.record_duplicate();
+                        continue;
+                    }
+                    best_cost.insert(child.clone(), child_cost);
+                }
+                parents.push((node, pour));
+                let estimate = child_cost + config.estimate(&child);
+                states.push(child);
+                open.push((Reverse(estimate), child_cost, Reverse(states.len() - 1)));
+            }
+        }
+        None
+    }
+}
+
+impl SolverStrategy for Beam {
+    fn name(&self) -> &'static str {
+        "beam"
+    }
+
+    fn is_optimal(&self) -> bool {
+        false
+    }
+
+    fn solve(&self, level: &WaterSorting, config: &SolverConfig, stats: &mut SolverStats) -> Option<Vec<Pour>> {
+        if level.win() {
+            return Some(Vec::new());
+        }
+        let mut parents = vec![(0, Pour::new(0, 0))];
+        let mut visited = HashSet::new();
+        visited.insert(level.clone());
+        let mut layer = vec![(level.clone(), 0usize)];
+
+        for depth in 0..config.max_depth {
+            let mut next = Vec::new();
+            for (state, node) in &layer {
+                if stats.nodes_expanded >= config.max_nodes {
+                    stats.search_truncated = true;
+                    return None;
+                }
+                let children = successors(state, config);
+                stats.record_expansion(depth, children.len());
+                for (pour, child) in children {
+                    if config.pruning.deduplicate_states && !visited.insert(child.clone()) {
+                        stats.record_duplicate();
+                        continue;
+                    }
+                    parents.push((*node, pour));
+                    if child.win() {
+                        return Some(path_to(&parents, parents.len() - 1));
+                    }
+                    next.push((child, parents.len() - 1));
+                }
+            }
+            if next.is_empty() {
+                return None;
+            }
+            next.sort_by_key(|(state, _)| remaining_pours_estimate(state));
+            if next.len() > config.beam_width {
+                stats.search_truncated = true;
+                next.truncate(config.beam_width);
+            }
+            stats.record_live_states(level, visited.len() + next.len());
+            layer = next;
+        }
+        stats.search_truncated = true;
+        None
+    }
+}
+
+#[cfg(test)]
+mod strategy_tests {
+    use crate::{Color, Pour, WaterSolver, WaterSorting};
+    use crate::rules::RuleSet;
+    use crate::strategy::{remaining_pours_estimate, Algorithm, MoveOrdering, PruningRules, SolverConfig};
+
+    fn level() -> WaterSorting {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Yellow, Color::Magenta, Color::Brown, Color::Yellow);
+        w.init_bottle_with_four_colors(Color::Magenta, Color::Magenta, Color::Brown, Color::Yellow);
+        w.init_bottle_with_four_colors(Color::Brown, Color::Brown, Color::Yellow, Color::Magenta);
+        w.init_empty_bottle();
+        w.init_empty_bottle();
+        w
+    }
+
+    fn wins(level: &WaterSorting, pours: &[Pour]) -> bool {
+        let mut w = level.clone();
+        pours.iter().for_each(|p| w.pour(p.from, p.to));
+        w.win()
+    }
+
+    #[test]
+    fn every_algorithm_solves_the_level() {
+        let w = level();
+        for algorithm in [Algorithm::Dfs, Algorithm::Bfs, Algorithm::AStar, Algorithm::Beam] {
+            let solver = WaterSolver::with_config(&w, SolverConfig::with_algorithm(algorithm));
+
+            let (solution, stats) = solver.solve_configured();
+
+            assert!(wins(&w, &solution), "{} did not solve the level", algorithm);
+            assert!(stats.nodes_expanded > 0);
+        }
+    }
+
+    #[test]
+    fn optimal_algorithms_agree_on_solution_length() {
+        let w = level();
+        let bfs = WaterSolver::with_config(&w, SolverConfig::with_algorithm(Algorithm::Bfs)).solve_configured().0;
+        let astar = WaterSolver::with_config(&w, SolverConfig::with_algorithm(Algorithm::AStar)).solve_configured().0;
+        let config = SolverConfig { require_optimal: true, ..SolverConfig::with_algorithm(Algorithm::Dfs) };
+        let iterative = WaterSolver::with_config(&w, config).solve_configured().0;
+
+        assert_eq!(bfs.len(), astar.len());
+        assert_eq!(bfs.len(), iterative.len());
+    }
+
+    #[test]
+    fn require_optimal_replaces_beam_with_an_optimal_strategy() {
+        let config = SolverConfig { require_optimal: true, ..SolverConfig::with_algorithm(Algorithm::Beam) };
+
+        assert!(config.strategy().is_optimal());
+        assert!(!SolverConfig::with_algorithm(Algorithm::Beam).strategy().is_optimal());
+    }
+
+    #[test]
+    fn require_optimal_does_not_prune_the_shortest_solution() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Red, Color::Blue);
+        w.init_bottle_with_colors(2, &[Color::Blue, Color::Red]);
+        w.init_bottle_with_colors(4, &[Color::Red, Color::Red]);
+        w.init_empty_bottle_with_capacity(2);
+        let pruned = WaterSolver::with_config(&w, SolverConfig::with_algorithm(Algorithm::Bfs)).solve_configured().0;
+
+        for algorithm in [Algorithm::Dfs, Algorithm::Bfs, Algorithm::AStar] {
+            let config = SolverConfig { require_optimal: true, ..SolverConfig::with_algorithm(algorithm) };
+
+            let solution = WaterSolver::with_config(&w, config).solve_configured().0;
+
+            assert!(wins(&w, &solution));
+            assert_eq!((solution.len(), pruned.len()), (4, 5), "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn optimal_search_ignores_the_estimate_under_other_rules() {
+        let ball_sort = level().under_rules(RuleSet::ball_sort());
+        let config = SolverConfig { require_optimal: true, ..SolverConfig::with_algorithm(Algorithm::AStar) };
+
+        assert_eq!(config.estimate(&ball_sort), 0);
+        assert_eq!(config.estimate(&level()), remaining_pours_estimate(&level()));
+        assert!(SolverConfig::with_algorithm(Algorithm::AStar).estimate(&ball_sort) > 0);
+    }
+
+    #[test]
+    fn node_limit_stops_the_search() {
+        let w = level();
+        let config = SolverConfig { max_nodes: 3, ..SolverConfig::with_algorithm(Algorithm::Bfs) };
+
+        let (solution, stats) = WaterSolver::with_config(&w, config).solve_configured();
+
+        assert!(solution.is_empty());
+        assert!(stats.search_truncated);
+        assert_eq!(stats.nodes_expanded, 3);
+    }
+
+    #[test]
+    fn depth_limit_is_respected() {
+        let w = level();
+        let config = SolverConfig { max_depth: 2, ..SolverConfig::with_algorithm(Algorithm::AStar) };
+
+        let (solution, stats) = WaterSolver::with_config(&w, config).solve_configured();
+
+        assert!(solution.is_empty());
+        assert!(stats.search_truncated);
+    }
+
+    #[test]
+    fn depth_first_solution_is_not_truncated_by_cut_branches() {
+        let w = level();
+        let shortest = WaterSolver::with_config(&w, SolverConfig::with_algorithm(Algorithm::Bfs)).solve_configured().0;
+        let config = SolverConfig { max_depth: shortest.len(), ..SolverConfig::with_algorithm(Algorithm::Dfs) };
+
+        let (solution, stats) = WaterSolver::with_config(&w, config).solve_configured();
+
+        assert!(wins(&w, &solution));
+        assert!(!stats.search_truncated);
+    }
+
+    #[test]
+    fn move_ordering_and_pruning_still_find_solution() {
+        let w = level();
+        for move_ordering in [MoveOrdering::LargestPourFirst, MoveOrdering::CompletingFirst] {
+            let config = SolverConfig { move_ordering, pruning: PruningRules::none(), max_depth: 12, ..SolverConfig::new() };
+
+            let solution = WaterSolver::with_config(&w, config).solve_configured().0;
+
+            assert!(wins(&w, &solution));
+        }
+    }
+
+    #[test]
+    fn empty_bottles_of_different_sizes_are_kept_apart() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Blue, Color::Red, Color::Red, Color::Red);
+        w.init_bottle_with_four_colors(Color::Red, Color::Blue, Color::Blue, Color::Blue);
+        w.init_empty_bottle_with_capacity(2);
+        w.init_empty_bottle();
+
+        let solution = WaterSolver::new(&w).solve_configured().0;
+
+        assert!(wins(&w, &solution));
+        assert_eq!(WaterSolver::new(&w).par(), Some(3));
+    }
+
+    #[test]
+    fn algorithm_can_be_parsed_from_text() {
+        assert_eq!("bfs".parse::<Algorithm>(), Ok(Algorithm::Bfs));
+        assert_eq!("A*".parse::<Algorithm>(), Ok(Algorithm::AStar));
+        assert!("random".parse::<Algorithm>().is_err());
+    }
+
+    #[test]
+    fn estimate_is_zero_only_for_solved_level() {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Red, Color::Red);
+        w.init_empty_bottle();
+        assert_eq!(remaining_pours_estimate(&w), 0);
+
+        assert!(remaining_pours_estimate(&level()) > 0);
+    }
+}
@@ -0,0 +1,376 @@
+use std::cell::Cell;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use crate::WaterSorting;
+
+/// Source of time for timed games, in milliseconds from any fixed point.
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+
+    // moves the clock to `now_ms`, false for clocks that keep their own time
+    fn set_now_ms(&self, _now_ms: u64) -> bool {
+        false
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SystemClock {
+    start: std::time::Instant,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock { start: std::time::Instant::now() }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
+
+// the browser clock, `std::time` is not available in wasm
+#[cfg(target_arch = "wasm32")]
+struct DateClock;
+
+#[cfg(target_arch = "wasm32")]
+impl Clock for DateClock {
+    fn now_ms(&self) -> u64 {
+        crate::stats::now_ms() as u64
+    }
+}
+
+/// Clock that only moves when told to; clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock(Rc<Cell<u64>>);
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, now_ms: u64) {
+        self.0.set(now_ms);
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.0.set(self.0.get() + ms);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.0.get()
+    }
+
+    fn set_now_ms(&self, now_ms: u64) -> bool {
+        self.set(now_ms);
+        true
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TimerMode {
+    // the game is lost when the time limit runs out
+    Countdown,
+    // time is only measured
+    Stopwatch,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimerConfig {
+    pub mode: TimerMode,
+    // time to solve the level in countdown mode
+    pub limit_ms: u64,
+    // added to the elapsed time for every pour and undo, negative values are bonuses
+    pub pour_penalty_ms: i64,
+    pub undo_penalty_ms: i64,
+}
+
+#[wasm_bindgen]
+impl TimerConfig {
+    pub fn countdown(limit_ms: u64) -> Self {
+        TimerConfig { mode: TimerMode::Countdown, limit_ms, pour_penalty_ms: 0, undo_penalty_ms: 0 }
+    }
+
+    pub fn stopwatch() -> Self {
+        TimerConfig { mode: TimerMode::Stopwatch, ..Self::countdown(0) }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TimedOutcome {
+    Won,
+    Lost,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimedResult {
+    pub outcome: TimedOutcome,
+    // elapsed time with penalties and bonuses, at most the limit of a countdown
+    pub time_ms: u64,
+    pub moves: usize,
+}
+
+impl Display for TimedResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let outcome = match self.outcome {
+            TimedOutcome::Won => "won",
+            TimedOutcome::Lost => "lost",
+        };
+        write!(f, "{} in {}.{:01}s after {} moves", outcome, self.time_ms / 1000, self.time_ms % 1000 / 100, self.moves)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Timer {
+    clock: Rc<dyn Clock>,
+    config: TimerConfig,
+    started_ms: u64,
+    penalty_ms: i64,
+    // elapsed time when the game ended by a pour
+    finished_ms: Option<u64>,
+}
+
+impl Timer {
+    fn elapsed_ms(&self) -> u64 {
+        if let Some(finished) = self.finished_ms {
+            return finished;
+        }
+        let elapsed = (self.clock.now_ms().saturating_sub(self.started_ms) as i64 + self.penalty_ms).max(0) as u64;
+        match self.config.mode {
+            TimerMode::Countdown => elapsed.min(self.config.limit_ms),
+            TimerMode::Stopwatch => elapsed,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WaterSorting {
+    /// Starts timing the game with the clock of the platform, `Date.now` in wasm.
+    pub fn start_timer(&mut self, config: TimerConfig) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.start_timer_with_clock(config, SystemClock::new());
+        #[cfg(target_arch = "wasm32")]
+        self.start_timer_with_clock(config, DateClock);
+    }
+
+    /// Starts timing the game with a clock that only moves with `set_now_ms`, so JS can supply its own time.
+    pub fn start_timer_with_manual_clock(&mut self, config: TimerConfig, now_ms: u64) {
+        let clock = ManualClock::new();
+        clock.set(now_ms);
+        self.start_timer_with_clock(config, clock);
+    }
+
+    /// Sets the time of a game timed by a `ManualClock`, e.g. one started with `start_timer_with_manual_clock`,
+    /// false for any other game.
+    pub fn set_now_ms(&mut self, now_ms: u64) -> bool {
+        self.timer.as_ref().is_some_and(|timer| timer.clock.set_now_ms(now_ms))
+    }
+
+    /// Time played so far with penalties and bonuses, the final time once the game is over.
+    pub fn elapsed_ms(&self) -> Option<u64> {
+        self.timer.as_ref().map(Timer::elapsed_ms)
+    }
+
+    /// Time left of a countdown.
+    pub fn remaining_ms(&self) -> Option<u64> {
+        let timer = self.timer.as_ref().filter(|timer| timer.config.mode == TimerMode::Countdown)?;
+        Some(timer.config.limit_ms - timer.elapsed_ms())
+    }
+
+    pub fn time_up(&self) -> bool {
+        self.timer.as_ref().is_some_and(|timer| timer.finished_ms.is_none()) && self.remaining_ms() == Some(0) && !self.win()
+    }
+
+    /// Outcome and final time of a timed game that is over.
+    pub fn timed_result(&self) -> Option<TimedResult> {
+        let time_ms = self.elapsed_ms()?;
+        let outcome = if self.win() {
+            TimedOutcome::Won
+        } else if self.lost() {
+            TimedOutcome::Lost
+        } else {
+            return None;
+        };
+        Some(TimedResult { outcome, time_ms, moves: self.moves })
+    }
+}
+
+impl WaterSorting {
+    /// Starts timing the game with `clock`, e.g. a `ManualClock` in tests. Not exported to wasm,
+    /// where `start_timer_with_manual_clock` takes the time from JS instead.
+    pub fn start_timer_with_clock(&mut self, config: TimerConfig, clock: impl Clock + 'static) {
+        let started_ms = clock.now_ms();
+        self.timer = Some(Timer { clock: Rc::new(clock), config, started_ms, penalty_ms: 0, finished_ms: None });
+    }
+
+    // penalties are added first, so a pour that wins stops the clock with its own penalty
+    pub(crate) fn time_pour(&mut self) {
+        let over = self.win() || self.lost();
+        if let Some(timer) = &mut self.timer {
+            timer.penalty_ms += timer.config.pour_penalty_ms;
+            if over {
+                timer.finished_ms = Some(timer.elapsed_ms());
+            }
+        }
+    }
+
+    // an undo that reverts the pour which ended the game resumes the clock from the final time,
+    // so the time between that pour and the undo is not counted
+    pub(crate) fn time_undo(&mut self) {
+        let over = self.win() || self.lost();
+        if let Some(timer) = &mut self.timer {
+            timer.penalty_ms += timer.config.undo_penalty_ms;
+            if let Some(finished) = timer.finished_ms.filter(|_| !over) {
+                timer.started_ms = timer.clock.now_ms();
+                timer.penalty_ms = finished as i64 + timer.config.undo_penalty_ms;
+                timer.finished_ms = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod timer_tests {
+    use crate::{Color, WaterSorting};
+    use crate::rules::PourRejection;
+    use crate::timer::{ManualClock, TimedOutcome, TimedResult, TimerConfig};
+
+    // solvable in three pours: 0 -> 2, 1 -> 0, 2 -> 1
+    fn level() -> WaterSorting {
+        let mut w = WaterSorting::new();
+        w.init_bottle_with_four_colors(Color::Red, Color::Red, Color::Blue, Color::Blue);
+        w.init_bottle_with_four_colors(Color::Blue, Color::Blue, Color::Red, Color::Red);
+        w.init_empty_bottle();
+        w
+    }
+
+    #[test]
+    fn stopwatch_reports_the_final_time_of_a_win() {
+        let clock = ManualClock::new();
+        clock.set(500);
+        let mut w = level();
+        w.start_timer_with_clock(TimerConfig::stopwatch(), clock.clone());
+
+        for (from, to) in [(0, 2), (1, 0), (2, 1)] {
+            clock.advance(2_000);
+            w.pour(from, to);
+        }
+        clock.advance(10_000);
+
+        assert_eq!(w.elapsed_ms(), Some(6_000));
+        assert_eq!(w.remaining_ms(), None);
+        assert_eq!(w.timed_result(), Some(TimedResult { outcome: TimedOutcome::Won, time_ms: 6_000, moves: 3 }));
+    }
+
+    #[test]
+    fn countdown_running_out_loses_the_game() {
+        let clock = ManualClock::new();
+        let mut w = level();
+        w.start_timer_with_clock(TimerConfig::countdown(5_000), clock.clone());
+
+        w.pour(0, 2);
+        clock.advance(4_000);
+        assert_eq!(w.remaining_ms(), Some(1_000));
+        assert_eq!(w.timed_result(), None);
+        clock.advance(1_500);
+
+        assert!(w.time_up());
+        assert!(w.lost());
+        assert_eq!(w.try_pour(1, 0), Err(PourRejection::TimeUp));
+        assert_eq!(w.timed_result().unwrap().outcome, TimedOutcome::Lost);
+        assert_eq!(w.timed_result().unwrap().time_ms, 5_000);
+    }
+
+    #[test]
+    fn pours_and_undos_adjust_the_time() {
+        let clock = ManualClock::new();
+        let mut w = level();
+        w.start_timer_with_clock(TimerConfig { pour_penalty_ms: -1_000, undo_penalty_ms: 3_000, ..TimerConfig::countdown(10_000) }, clock.clone());
+
+        clock.advance(2_000);
+        w.pour(0, 2);
+        assert_eq!(w.remaining_ms(), Some(9_000));
+        w.undo();
+
+        assert_eq!(w.remaining_ms(), Some(6_000));
+    }
+
+    #[test]
+    fn undo_long_after_a_win_does_not_count_the_idle_time() {
+        let clock = ManualClock::new();
+        let mut w = level();
+        w.start_timer_with_clock(TimerConfig { undo_penalty_ms: 500, ..TimerConfig::stopwatch() }, clock.clone());
+
+        for (from, to) in [(0, 2), (1, 0), (2, 1)] {
+            clock.advance(2_000);
+            w.pour(from, to);
+        }
+        clock.advance(60_000);
+        w.undo();
+        assert_eq!(w.elapsed_ms(), Some(6_500));
+
+        clock.advance(1_000);
+        w.pour(2, 1);
+        clock.advance(10_000);
+
+        assert!(w.win());
+        assert_eq!(w.timed_result().unwrap().time_ms, 7_500);
+    }
+
+    #[test]
+    fn undo_of_a_pour_after_the_win_keeps_the_final_time() {
+        let clock = ManualClock::new();
+        let mut w = level();
+        w.start_timer_with_clock(TimerConfig::stopwatch(), clock.clone());
+
+        for (from, to) in [(0, 2), (1, 0), (2, 1), (0, 2)] {
+            clock.advance(2_000);
+            w.pour(from, to);
+        }
+        clock.advance(60_000);
+        w.undo();
+
+        assert!(w.win());
+        assert_eq!(w.elapsed_ms(), Some(6_000));
+    }
+
+    #[test]
+    fn manual_clock_takes_the_time_from_the_caller() {
+        let mut w = level();
+        assert!(!w.set_now_ms(1_000));
+        w.start_timer_with_manual_clock(TimerConfig::countdown(5_000), 1_000);
+
+        w.pour(0, 2);
+        assert!(w.set_now_ms(3_500));
+
+        assert_eq!(w.elapsed_ms(), Some(2_500));
+        assert_eq!(w.remaining_ms(), Some(2_500));
+    }
+
+    #[test]
+    fn untimed_games_have_no_time() {
+        let mut w = level();
+
+        assert!(!w.set_now_ms(1_000));
+        assert_eq!(w.elapsed_ms(), None);
+        assert!(!w.time_up());
+        assert_eq!(w.timed_result(), None);
+    }
+}
@@ -1,4 +1,4 @@
-import {Color, WaterSorting, WaterSolver} from "wasm-water-sort";
+import {Campaign, WaterSolver, StarThresholds, classic_level, classic_level_par, daily_level} from "wasm-water-sort";
 import { memory } from "../pkg/water_sort_bg.wasm";
 
 const SIZE = 25;
@@ -14,46 +14,108 @@ const BROWN = '#7b2525'
 const YELLOW = '#F0F000'
 const GREEN = '#008000'
 const MAGENTA = '#7f1894'
-const LIME = '#7aa402'
 const TEAL = '#55b08d'
 const PURPLE = '#ab64d4'
 const LIGHTBLUE ='#2688ab'
 const PEACH = '#cb9486'
 const OLIVE = '#194e24'
-const colors = [EMPTY, BLUE, RED, GRAY, ORANGE, BROWN, YELLOW, GREEN, MAGENTA, LIME, TEAL, PURPLE, LIGHTBLUE, PEACH, OLIVE];
-const waterSorting = WaterSorting.new();
+// indices follow WaterSorting::map_color_to_u8
+const RAINBOW = 'rainbow'
+const RAINBOW_STOPS = ['#fb0606', '#F08000', '#F0F000', '#008000', '#000080', '#7f1894'];
+// set on the layers of a capped bottle, see CAPPED_MARKER
+const CAPPED = 0x80;
+const colors = [EMPTY, BLUE, RED, GRAY, ORANGE, BROWN, YELLOW, GREEN, MAGENTA, TEAL, PURPLE, LIGHTBLUE, PEACH, OLIVE, RAINBOW];
 const success = new Audio('success.mp3');
 
-const initialize = (w) => {
-    w.init_bottle_with_four_colors(Color.Red, Color.Red, Color.Orange, Color.Blue);
-    w.init_bottle_with_four_colors(Color.Peach, Color.Blue, Color.Peach, Color.Orange);
-    w.init_bottle_with_four_colors(Color.Peach, Color.Blue, Color.Red, Color.Peach);
-    w.init_bottle_with_four_colors(Color.Orange, Color.Teal, Color.Red, Color.Teal);
-    w.init_bottle_with_four_colors(Color.Blue, Color.Teal, Color.Teal, Color.Orange);
-    w.init_empty_bottle();
-    w.init_empty_bottle();
+const query = new URLSearchParams(window.location.search);
+// `?daily` plays the puzzle of the day, the same for everyone on a given date
+const daily = query.has('daily');
+// `?campaign` plays the bundled levels in order, progress is kept in localStorage
+const SAVE_KEY = 'water-sort-campaign';
+const campaign = query.has('campaign') ? Campaign.classic() : undefined;
+if (campaign && localStorage.getItem(SAVE_KEY)) {
+    campaign.restore_blob(localStorage.getItem(SAVE_KEY));
+}
+let campaignLevel = 0;
+let recorded = false;
+// extra bottles the player may add to a level, `?extra=N`; campaign levels keep the budget of
+// their rules, other levels get one
+const extraBottles = query.has('extra') ? Number(query.get('extra')) : undefined;
+// identifies the level being played and holds the par its pack records, if any
+let levelKey;
+let packPar;
+const loadLevel = () => {
+    if (campaign) {
+        campaignLevel = campaign.next_level() ?? 0;
+        levelKey = `campaign ${campaignLevel}`;
+        packPar = campaign.par(campaignLevel);
+        return campaign.level(campaignLevel);
+    }
+    if (daily) {
+        const today = new Date();
+        const level = daily_level(today.getFullYear(), today.getMonth() + 1, today.getDate());
+        if (level) {
+            levelKey = `daily ${today.toDateString()}`;
+            packPar = undefined;
+            return level;
+        }
+        // no puzzle for this date, play the built-in level instead
+        console.error(`no daily puzzle for ${today.toDateString()}`);
+    }
+    levelKey = 'classic web';
+    packPar = classic_level_par("web");
+    return classic_level("web");
+};
+const createLevel = () => {
+    const w = loadLevel();
+    if (extraBottles !== undefined) {
+        w.allow_extra_bottles(extraBottles);
+    } else if (!campaign) {
+        w.allow_extra_bottles(1);
+    }
+    return w;
 };
-initialize(waterSorting);
+let waterSorting = createLevel();
+// shortest solution of the level, stars are awarded against it; the solver only runs for levels
+// without a par in their pack, once per level
+const solvedPar = new Map();
+const computePar = (w) => {
+    if (packPar !== undefined) return packPar;
+    if (!solvedPar.has(levelKey)) {
+        const solver = WaterSolver.new(w);
+        solvedPar.set(levelKey, solver.par());
+        solver.free();
+    }
+    return solvedPar.get(levelKey);
+};
+let par = computePar(waterSorting);
+const starThresholds = StarThresholds.new();
 
 const canvas = document.getElementById('water-sorting-canvas');
 const undo_btn = document.getElementById('undo-btn');
 const reset_btn = document.getElementById('reset-btn');
 const solve_btn = document.getElementById('solve-btn');
+const extra_btn = document.getElementById('extra-btn');
 const ctx = canvas.getContext('2d');
 
-const bottles_count = waterSorting.bottles_count();
-canvas.width = PADDING+(bottles_count + 1)*(SIZE+SPACE)+SPACE;
-canvas.height = 5*SIZE+SPACE;
+let bottles_count = 0;
+const positions = [];
+// levels differ in the number of bottles, so the canvas is laid out for each one
+const layoutBottles = () => {
+    bottles_count = waterSorting.bottles_count();
+    canvas.width = PADDING+(bottles_count + 1)*(SIZE+SPACE)+SPACE;
+    canvas.height = 5*SIZE+SPACE;
+    positions.splice(0, positions.length);
+    for(let i = 0; i<bottles_count;i++)
+    {
+        let x = PADDING + i * (SIZE+SPACE);
+        positions.push({'x1': x,'x2':x+SIZE});
+    }
+};
+layoutBottles();
 
 const selected = [];
 
-const positions = [];
-for(let i = 0; i<bottles_count;i++)
-{
-    let x = PADDING + i * (SIZE+SPACE);
-    positions.push({'x1': x,'x2':x+SIZE});
-}
-
 const getMousePos = (canvas, evt) => {
     const rect = canvas.getBoundingClientRect();
     return {
@@ -85,6 +147,12 @@ const reset = () => {
 }
 reset_btn.onclick = reset;
 
+let extra_requested = false;
+const extra = () => {
+    extra_requested = true;
+}
+extra_btn.onclick = extra;
+
 const perform_move = (moves, index) => {
     if (index < 0 || index >= moves.length) return;
     const move = moves[index];
@@ -105,6 +173,13 @@ const solve = (ws) => {
     for (let i = 1; i < count*2; i+=2) {
         solution_moves.push({from: moves[i*4], to: moves[(i+1)*4]});
     }
+    const stats = waterSolver.stats();
+    console.log(`nodes expanded: ${stats.nodes_expanded}, deduplicated: ${stats.states_deduplicated}, ` +
+        `max depth: ${stats.max_depth}, branching: ${stats.average_branching_factor().toFixed(2)}, ` +
+        `time: ${stats.time_ms.toFixed(1)} ms, memory: ${stats.peak_memory_bytes} B`);
+    stats.free();
+    // the pours were copied out of the solver's memory above
+    waterSolver.free();
     setTimeout(perform_move, 100, solution_moves, 0);
 }
 
@@ -137,13 +212,23 @@ const drawGame = () => {
         waterSorting.pour(selected[0], selected[1]);
         selected.splice(0, 2);
     }
+    function perform_extra() {
+        extra_requested = false;
+        selected.splice(0, 2);
+        waterSorting.add_extra_bottle(waterSorting.capacity());
+    }
+
     function perform_reset() {
         reset_requested = false;
-        waterSorting.reset();
-        initialize(waterSorting);
+        waterSorting.free();
+        waterSorting = createLevel();
+        par = computePar(waterSorting);
+        recorded = false;
+        layoutBottles();
     }
 
     waterSorting.undo_available() ? undo_btn.removeAttribute("disabled") : undo_btn.setAttribute("disabled", "disabled");
+    waterSorting.extra_bottles_left() > 0 ? extra_btn.removeAttribute("disabled") : extra_btn.setAttribute("disabled", "disabled");
     clear();
 
     if (reset_requested) {
@@ -153,24 +238,52 @@ const drawGame = () => {
     if (undo_requested) {
         perform_undo();
     }
+    else if (extra_requested) {
+        perform_extra();
+    }
     else if (selected.length === 2) {
         perform_pouring();
     }
+    // extra bottles come and go during the game
+    if (waterSorting.bottles_count() !== bottles_count) {
+        layoutBottles();
+    }
     if (waterSorting.win()) {
+        if (campaign && !recorded) {
+            recorded = true;
+            const rating = campaign.complete(campaignLevel, waterSorting);
+            if (rating) rating.free();
+            localStorage.setItem(SAVE_KEY, campaign.save_blob());
+        }
         drawWin();
         success.play().then(() => setTimeout(() => reset_requested = true, 3000));
     } else {
         drawBottles(waterSorting.bottles());
+        drawLocks(waterSorting.bottle_color_locks());
+        drawFrost(waterSorting.frozen_layers());
+        if (waterSorting.lost()) {
+            drawMessage(waterSorting.time_up() ? "Time is up!" : "No moves left!");
+        }
     }
     requestAnimationFrame(drawGame);
 }
 
-const drawWin = () => {
+const drawMessage = (message) => {
     ctx.fillStyle = colors[4];
     ctx.font = "40px serif"
-    const win = "You win!";
-    const measure = ctx.measureText(win);
-    ctx.fillText(win, (canvas.width - measure.width) / 2,canvas.height - 20, measure.width);
+    const measure = ctx.measureText(message);
+    ctx.fillText(message, (canvas.width - measure.width) / 2, canvas.height - 20, measure.width);
+}
+
+const drawWin = () => {
+    drawMessage("You win!");
+    if (par === undefined) return;
+    const rating = waterSorting.stars(par, starThresholds);
+    if (rating === undefined) return;
+    const stars = "★".repeat(rating.stars) + "☆".repeat(3 - rating.stars);
+    const starsMeasure = ctx.measureText(stars);
+    ctx.fillText(stars, (canvas.width - starsMeasure.width) / 2, canvas.height - 70, starsMeasure.width);
+    rating.free();
 }
 
 const drawBottles = (bottlesPtr) => {
@@ -178,7 +291,41 @@ const drawBottles = (bottlesPtr) => {
     for (let i = 0; i < bottles_count; i++) {
         drawBottle(i);
         for (let j = 0; j < 4; j++) {
-            drawFluid(i, 4 - j, bottles[i*4+j])
+            drawFluid(i, 4 - j, bottles[i*4+j] & ~CAPPED)
+        }
+        if (bottles[i*4] & CAPPED) drawCap(i);
+    }
+}
+
+const drawCap = (x) => {
+    ctx.fillStyle = '#333';
+    let padding = selected[0] === x ? SELECTED_PADDING : 0;
+    ctx.fillRect(PADDING + x * (SIZE+SPACE) - 3, SIZE - 4 + padding, SIZE + 6, 5);
+}
+
+// a bar under the bottle in the only color it takes
+const drawLocks = (locksPtr) => {
+    const locks = new Uint8Array(memory.buffer, locksPtr, bottles_count);
+    for (let i = 0; i < bottles_count; i++) {
+        if (locks[i] === 0) continue;
+        ctx.fillStyle = colors[locks[i]] === RAINBOW ? rainbowFill(PADDING + i * (SIZE+SPACE), 5*SIZE) : colors[locks[i]];
+        ctx.fillRect(PADDING + i * (SIZE+SPACE), 5*SIZE+3, SIZE, 5);
+    }
+}
+
+// frozen layers get a layer of frost until they thaw
+const drawFrost = (frozenPtr) => {
+    const frozen = new Uint8Array(memory.buffer, frozenPtr, bottles_count);
+    ctx.fillStyle = 'rgba(220, 240, 255, 0.6)';
+    ctx.strokeStyle = '#9ad0f5';
+    for (let i = 0; i < bottles_count; i++) {
+        let padding = selected[0] === i ? SELECTED_PADDING : 0;
+        for (let j = 0; j < 4; j++) {
+            if ((frozen[i] & (1 << j)) === 0) continue;
+            const x = PADDING + i * (SIZE+SPACE);
+            const y = (4 - j) * SIZE + padding;
+            ctx.fillRect(x, y, SIZE, SIZE);
+            ctx.strokeRect(x + 1, y + 1, SIZE - 2, SIZE - 2);
         }
     }
 }
@@ -199,9 +346,17 @@ const drawBottle = (x) => {
     ctx.stroke();
 }
 
+const rainbowFill = (left, top) => {
+    const gradient = ctx.createLinearGradient(left, top, left, top + SIZE);
+    RAINBOW_STOPS.forEach((stop, i) => gradient.addColorStop(i / (RAINBOW_STOPS.length - 1), stop));
+    return gradient;
+}
+
 const drawFluid = (x, y, c) => {
-    ctx.fillStyle = colors[c];
     let padding = selected[0] === x ? SELECTED_PADDING : 0;
+    ctx.fillStyle = colors[c] === RAINBOW
+        ? rainbowFill(PADDING + x * (SIZE+SPACE), y * SIZE + padding)
+        : colors[c];
 
     ctx.fillRect(
         PADDING + x * (SIZE+SPACE),